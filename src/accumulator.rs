@@ -0,0 +1,34 @@
+use crate::fstats_f64::Stats;
+
+/// A streaming structure fed one value at a time.
+///
+/// Tuples of accumulators are accumulators too, so a collector can feed the same stream to
+/// several of them at once, e.g. `(Stats, P2Quantile)` for the mean, stddev and p99.
+pub trait Accumulator {
+    fn push(&mut self, x: f64);
+
+    fn push_vec(&mut self, v: Vec<f64>) {
+        v.iter().for_each(|x| self.push(*x));
+    }
+}
+
+impl Accumulator for Stats {
+    fn push(&mut self, x: f64) {
+        Stats::push(self, x);
+    }
+}
+
+impl<A: Accumulator, B: Accumulator> Accumulator for (A, B) {
+    fn push(&mut self, x: f64) {
+        self.0.push(x);
+        self.1.push(x);
+    }
+}
+
+impl<A: Accumulator, B: Accumulator, C: Accumulator> Accumulator for (A, B, C) {
+    fn push(&mut self, x: f64) {
+        self.0.push(x);
+        self.1.push(x);
+        self.2.push(x);
+    }
+}
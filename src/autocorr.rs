@@ -0,0 +1,316 @@
+use std::collections::VecDeque;
+use std::f64;
+
+use crate::error::Error;
+
+/// Autocorrelation of a stream at lags 1 to `max_lag`.
+///
+/// Alongside the sum and sum of squares it keeps, for every lag k, the sum of products of
+/// values k steps apart. A push or an expiry from the window touches each of those sums once,
+/// so updates are O(max_lag), and the ACF is rebuilt from them on demand. The partial
+/// autocorrelations follow from the ACF by the Durbin-Levinson recursion.
+///
+/// The sums are taken over deviations from a shift near the mean, so that they do not cancel
+/// for values far from zero. The shift follows the mean by rebuilding the sums once per `len`
+/// updates, which keeps updates O(max_lag) amortized.
+#[derive(Clone, Debug)]
+pub struct Autocorrelation {
+    data: VecDeque<f64>,
+    window: Option<usize>,
+    shift: f64,
+    sum: f64,
+    sum_of_squares: f64,
+    // lagged[k - 1] is the sum of (x[t] - shift) * (x[t + k] - shift)
+    lagged: Vec<f64>,
+    // Pushes and removals since the sums were rebuilt around the mean
+    updates: usize,
+}
+
+impl Autocorrelation {
+    pub fn new(max_lag: usize) -> Result<Self, Error> {
+        if max_lag == 0 {
+            return Err(Error::InvalidParameter("max_lag must be positive"));
+        }
+        Ok(Autocorrelation {
+            data: VecDeque::new(),
+            window: None,
+            shift: 0.0,
+            sum: 0.0,
+            sum_of_squares: 0.0,
+            lagged: vec![0.0; max_lag],
+            updates: 0,
+        })
+    }
+
+    pub fn with_window(window: usize, max_lag: usize) -> Result<Self, Error> {
+        if window <= max_lag {
+            return Err(Error::InvalidParameter("window must be longer than max_lag"));
+        }
+        Ok(Autocorrelation {
+            window: Some(window),
+            ..Autocorrelation::new(max_lag)?
+        })
+    }
+
+    // Moves the shift to the current mean and sums the deviations from it again.
+    fn recenter(&mut self) {
+        let n = self.data.len();
+        self.shift += self.sum / n as f64;
+        let shift = self.shift;
+        let deviations: Vec<f64> = self.data.iter().map(|x| x - shift).collect();
+        self.sum = deviations.iter().sum();
+        self.sum_of_squares = deviations.iter().map(|d| d * d).sum();
+        for (k, lagged) in self.lagged.iter_mut().enumerate() {
+            *lagged = deviations.iter().zip(deviations.iter().skip(k + 1)).map(|(a, b)| a * b).sum();
+        }
+        self.updates = 0;
+    }
+
+    fn updated(&mut self) {
+        self.updates += 1;
+        if self.updates > self.data.len() && !self.data.is_empty() {
+            self.recenter();
+        }
+    }
+
+    pub fn push(&mut self, x: f64) {
+        if self.data.is_empty() {
+            self.reset();
+            self.shift = x;
+        }
+        let y = x - self.shift;
+        for (k, lagged) in self.lagged.iter_mut().enumerate() {
+            if let Some(previous) = self.data.len().checked_sub(k + 1).map(|i| self.data[i]) {
+                *lagged += (previous - self.shift) * y;
+            }
+        }
+        self.data.push_back(x);
+        self.sum += y;
+        self.sum_of_squares += y * y;
+        if self.window.is_some_and(|w| self.data.len() > w) {
+            self.pop_front();
+        } else {
+            self.updated();
+        }
+    }
+
+    pub fn push_vec(&mut self, v: Vec<f64>) {
+        v.iter().for_each(|x| self.push(*x));
+    }
+
+    /// Removes the oldest value.
+    pub fn pop_front(&mut self) -> Option<f64> {
+        let x = self.data.pop_front()?;
+        let y = x - self.shift;
+        for (k, lagged) in self.lagged.iter_mut().enumerate() {
+            if let Some(next) = self.data.get(k) {
+                *lagged -= y * (next - self.shift);
+            }
+        }
+        self.sum -= y;
+        self.sum_of_squares -= y * y;
+        self.updated();
+        Some(x)
+    }
+
+    pub fn reset(&mut self) {
+        self.data.clear();
+        self.shift = 0.0;
+        self.sum = 0.0;
+        self.sum_of_squares = 0.0;
+        self.lagged.iter_mut().for_each(|s| *s = 0.0);
+        self.updates = 0;
+    }
+
+    pub fn max_lag(&self) -> usize {
+        self.lagged.len()
+    }
+
+    pub fn window(&self) -> Option<usize> {
+        self.window
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Sample autocorrelations indexed by lag, starting with 1.0 at lag zero. `None` until
+    /// there are more values than `max_lag` or while the values are constant.
+    pub fn acf(&self) -> Option<Vec<f64>> {
+        let n = self.data.len();
+        if n <= self.max_lag() {
+            return None;
+        }
+        // Mean of the deviations from the shift
+        let mean = self.sum / n as f64;
+        let denominator = self.sum_of_squares - n as f64 * mean * mean;
+        if denominator <= 0.0 {
+            return None;
+        }
+        let mut acf = vec![1.0];
+        let (mut head, mut tail) = (0.0, 0.0);
+        for (k, lagged) in self.lagged.iter().enumerate() {
+            // Sums of the first and last k + 1 values, which have no partner k + 1 steps away
+            head += self.data[k] - self.shift;
+            tail += self.data[n - k - 1] - self.shift;
+            let pairs = (n - k - 1) as f64;
+            let centered = lagged - mean * (2.0 * self.sum - head - tail) + pairs * mean * mean;
+            acf.push(centered / denominator);
+        }
+        Some(acf)
+    }
+
+    pub fn autocorrelation(&self, lag: usize) -> Option<f64> {
+        if lag > self.max_lag() {
+            return None;
+        }
+        self.acf().map(|acf| acf[lag])
+    }
+
+    /// Partial autocorrelations indexed by lag, starting with 1.0 at lag zero.
+    pub fn pacf(&self) -> Option<Vec<f64>> {
+        let acf = self.acf()?;
+        let mut pacf = vec![1.0];
+        let mut phi: Vec<f64> = vec![];
+        for k in 1..acf.len() {
+            let numerator = acf[k] - phi.iter().enumerate().map(|(j, p)| p * acf[k - 1 - j]).sum::<f64>();
+            let denominator = 1.0 - phi.iter().enumerate().map(|(j, p)| p * acf[j + 1]).sum::<f64>();
+            if denominator <= 0.0 {
+                return None;
+            }
+            let phi_kk = numerator / denominator;
+            let previous = phi.clone();
+            phi.iter_mut().enumerate().for_each(|(j, p)| *p -= phi_kk * previous[k - 2 - j]);
+            phi.push(phi_kk);
+            pacf.push(phi_kk);
+        }
+        Some(pacf)
+    }
+
+    /// Ljung-Box statistic over lags 1 to `lags`, which is approximately chi-squared with
+    /// `lags` degrees of freedom when the values are independent.
+    pub fn ljung_box(&self, lags: usize) -> Option<f64> {
+        if lags == 0 || lags > self.max_lag() {
+            return None;
+        }
+        let acf = self.acf()?;
+        let n = self.data.len() as f64;
+        let sum: f64 = (1..=lags).map(|k| acf[k] * acf[k] / (n - k as f64)).sum();
+        Some(n * (n + 2.0) * sum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+    use super::Autocorrelation;
+    use crate::error::Error;
+    use crate::test_util::values;
+    use crate::stats::acf;
+
+    // AR(1) process x[t] = phi * x[t - 1] + noise.
+    fn ar1(n: usize, phi: f64, seed: u64) -> Vec<f64> {
+        let mut x = 0.0;
+        values(n, seed).iter().map(|e| {
+            x = phi * x + e - 0.5;
+            x
+        }).collect()
+    }
+
+    #[test]
+    fn acf_test() {
+        let vs = ar1(300, 0.7, 1);
+        let mut ac = Autocorrelation::new(5).unwrap();
+        ac.push_vec(vs[..5].to_vec());
+        assert_eq!(ac.acf(), None);
+        ac.push_vec(vs[5..].to_vec());
+
+        let expected = acf(&vs, 5).unwrap();
+        ac.acf().unwrap().iter().zip(expected.iter())
+            .for_each(|(a, e)| assert!( approx_eq!(f64, *a, *e, epsilon = 1e-9) ));
+        assert!((ac.autocorrelation(1).unwrap() - 0.7).abs() < 0.1);
+        assert_eq!(ac.autocorrelation(6), None);
+    }
+
+    #[test]
+    fn window_test() {
+        let window = 40;
+        let vs = ar1(200, -0.5, 2);
+        let mut ac = Autocorrelation::with_window(window, 3).unwrap();
+        for i in 0..vs.len() {
+            ac.push(vs[i]);
+            if i >= 10 {
+                let start = (i + 1).saturating_sub(window);
+                let expected = acf(&vs[start..=i], 3).unwrap();
+                ac.acf().unwrap().iter().zip(expected.iter())
+                    .for_each(|(a, e)| assert!( approx_eq!(f64, *a, *e, epsilon = 1e-9) ));
+            }
+        }
+        assert_eq!(ac.len(), window);
+    }
+
+    #[test]
+    fn pacf_test() {
+        let mut ac = Autocorrelation::new(4).unwrap();
+        ac.push_vec(ar1(2000, 0.6, 3));
+        let acf = ac.acf().unwrap();
+        let pacf = ac.pacf().unwrap();
+
+        assert_eq!(pacf[0], 1.0);
+        assert!( approx_eq!(f64, pacf[1], acf[1], epsilon = 1e-15) );
+        let phi22 = (acf[2] - acf[1] * acf[1]) / (1.0 - acf[1] * acf[1]);
+        assert!( approx_eq!(f64, pacf[2], phi22, epsilon = 1e-12) );
+        // An AR(1) process has no partial autocorrelation beyond lag one.
+        pacf[2..].iter().for_each(|p| assert!(p.abs() < 0.1));
+    }
+
+    #[test]
+    fn ljung_box_test() {
+        let mut noise = Autocorrelation::new(10).unwrap();
+        noise.push_vec(values(1000, 4));
+        let mut periodic = Autocorrelation::new(10).unwrap();
+        periodic.push_vec((0..1000).map(|i| (i % 7) as f64).collect());
+
+        // The 99% quantile of chi-squared with 10 degrees of freedom is about 23.2.
+        assert!(noise.ljung_box(10).unwrap() < 23.2);
+        assert!(periodic.ljung_box(10).unwrap() > 1000.0);
+        assert!(periodic.autocorrelation(7).unwrap() > 0.95);
+        assert_eq!(noise.ljung_box(11), None);
+    }
+
+    #[test]
+    fn offset_test() {
+        // A large level leaves nothing of sum(x²) - n * mean² in double precision.
+        let vs: Vec<f64> = (0..1000).map(|i| 1.0e8 + (i % 7) as f64).collect();
+        let window = 100;
+        let mut ac = Autocorrelation::with_window(window, 7).unwrap();
+        for i in 0..vs.len() {
+            ac.push(vs[i]);
+            if i >= window {
+                let expected = acf(&vs[i + 1 - window..=i], 7).unwrap();
+                ac.acf().unwrap().iter().zip(expected.iter())
+                    .for_each(|(a, e)| assert!( approx_eq!(f64, *a, *e, epsilon = 1e-9) ));
+            }
+        }
+        assert!(ac.autocorrelation(7).unwrap() > 0.9);
+
+        // A level that drifts away from the first value is followed by the shift.
+        let mut drift = Autocorrelation::with_window(50, 2).unwrap();
+        let vs: Vec<f64> = (0..2000).map(|i| 1.0e6 * i as f64 + (i % 3) as f64).collect();
+        drift.push_vec(vs.clone());
+        let expected = acf(&vs[1950..], 2).unwrap();
+        drift.acf().unwrap().iter().zip(expected.iter())
+            .for_each(|(a, e)| assert!( approx_eq!(f64, *a, *e, epsilon = 1e-6) ));
+    }
+
+    #[test]
+    fn parameter_test() {
+        assert_eq!(Autocorrelation::new(0).err(), Some(Error::InvalidParameter("max_lag must be positive")));
+        assert!(matches!(Autocorrelation::with_window(5, 5), Err(Error::InvalidParameter(_))));
+        assert!(Autocorrelation::with_window(6, 5).is_ok());
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::vec_deque::{self, VecDeque};
+use std::f64;
+
+use crate::error::Error;
+
+/// Running statistics over a stream of `(x, y)` pairs.
+///
+/// Like `Stats` it keeps the pairs so any of them can be removed again, and maintains the
+/// means of x and y with the sums of squares and products of their deviations, so every
+/// statistic, including the simple linear regression of y on x, is O(1). As in `MultiStats`
+/// these co-moments follow Welford updates and their inverse, which stay accurate where raw
+/// sums of products would cancel, for example with timestamps as x. With a window only the
+/// most recent pairs are kept and older ones are dropped as new ones arrive.
+#[derive(Clone, Debug, Default)]
+pub struct PairedStats {
+    data: VecDeque<(f64, f64)>,
+    window: Option<usize>,
+    mean_x: f64,
+    mean_y: f64,
+    // Sums of (x - mean_x)², (y - mean_y)² and (x - mean_x)(y - mean_y)
+    comoment_xx: f64,
+    comoment_yy: f64,
+    comoment_xy: f64,
+}
+
+impl PairedStats {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_window(window: usize) -> Result<Self, Error> {
+        if window == 0 {
+            return Err(Error::InvalidParameter("window must be positive"));
+        }
+        Ok(PairedStats {
+            window: Some(window),
+            ..Default::default()
+        })
+    }
+
+    // Called after the pair has been added to the data.
+    fn add_cache(&mut self, x: f64, y: f64) {
+        let n = self.data.len() as f64;
+        let (dx, dy) = (x - self.mean_x, y - self.mean_y);
+        self.mean_x += dx / n;
+        self.mean_y += dy / n;
+        self.comoment_xx += dx * (x - self.mean_x);
+        self.comoment_yy += dy * (y - self.mean_y);
+        self.comoment_xy += dx * (y - self.mean_y);
+    }
+
+    // Called after the pair has been taken out of the data.
+    fn del_cache(&mut self, x: f64, y: f64) {
+        if self.data.is_empty() {
+            self.reset();
+            return;
+        }
+        let n = self.data.len() as f64;
+        let (dx, dy) = (x - self.mean_x, y - self.mean_y);
+        self.mean_x -= dx / n;
+        self.mean_y -= dy / n;
+        self.comoment_xx = f64::max(0.0, self.comoment_xx - dx * (x - self.mean_x));
+        self.comoment_yy = f64::max(0.0, self.comoment_yy - dy * (y - self.mean_y));
+        self.comoment_xy -= dx * (y - self.mean_y);
+    }
+
+    pub fn push(&mut self, x: f64, y: f64) {
+        self.data.push_back((x, y));
+        self.add_cache(x, y);
+        if self.window.is_some_and(|w| self.data.len() > w) {
+            self.remove(0);
+        }
+    }
+
+    pub fn push_vec(&mut self, v: Vec<(f64, f64)>) {
+        v.iter().for_each(|(x, y)| self.push(*x, *y));
+    }
+
+    pub fn insert(&mut self, index: usize, x: f64, y: f64) {
+        self.data.insert(index, (x, y));
+        self.add_cache(x, y);
+        if self.window.is_some_and(|w| self.data.len() > w) {
+            self.remove(0);
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> (f64, f64) {
+        let (x, y) = self.data.remove(index).expect("index out of bounds");
+        self.del_cache(x, y);
+        (x, y)
+    }
+
+    pub fn pop(&mut self) -> Option<(f64, f64)> {
+        let option = self.data.pop_back();
+        if let Some((x, y)) = option {
+            self.del_cache(x, y);
+        }
+        option
+    }
+
+    /// Removes the `n` oldest pairs, or all of them if there are fewer.
+    pub fn trim(&mut self, n: usize) {
+        for _ in 0..usize::min(n, self.data.len()) {
+            self.remove(0);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.data.clear();
+        self.mean_x = 0.0;
+        self.mean_y = 0.0;
+        self.comoment_xx = 0.0;
+        self.comoment_yy = 0.0;
+        self.comoment_xy = 0.0;
+    }
+
+    pub fn get(&self, index: usize) -> Option<(f64, f64)> {
+        self.data.get(index).copied()
+    }
+
+    pub fn iter(&self) -> vec_deque::Iter<'_, (f64, f64)> {
+        self.data.iter()
+    }
+
+    pub fn window(&self) -> Option<usize> {
+        self.window
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn mean_x(&self) -> Option<f64> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.mean_x)
+        }
+    }
+
+    pub fn mean_y(&self) -> Option<f64> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.mean_y)
+        }
+    }
+
+    pub fn stddev_x(&self) -> Option<f64> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(f64::sqrt(self.comoment_xx / self.len() as f64))
+        }
+    }
+
+    pub fn stddev_y(&self) -> Option<f64> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(f64::sqrt(self.comoment_yy / self.len() as f64))
+        }
+    }
+
+    /// Population covariance, dividing by `n`.
+    pub fn covariance(&self) -> Option<f64> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.comoment_xy / self.len() as f64)
+        }
+    }
+
+    /// Sample covariance, dividing by `n - 1`.
+    pub fn sample_covariance(&self) -> Option<f64> {
+        if self.len() < 2 {
+            None
+        } else {
+            Some(self.comoment_xy / (self.len() - 1) as f64)
+        }
+    }
+
+    /// Pearson correlation coefficient, or `None` when either axis is constant.
+    pub fn correlation(&self) -> Option<f64> {
+        if self.len() < 2 || self.comoment_xx <= 0.0 || self.comoment_yy <= 0.0 {
+            None
+        } else {
+            Some((self.comoment_xy / f64::sqrt(self.comoment_xx * self.comoment_yy)).clamp(-1.0, 1.0))
+        }
+    }
+
+    /// Slope of the least squares line of y on x, or `None` when x is constant.
+    pub fn slope(&self) -> Option<f64> {
+        if self.len() < 2 || self.comoment_xx <= 0.0 {
+            None
+        } else {
+            Some(self.comoment_xy / self.comoment_xx)
+        }
+    }
+
+    pub fn intercept(&self) -> Option<f64> {
+        let slope = self.slope()?;
+        Some(self.mean_y - slope * self.mean_x)
+    }
+
+    pub fn predict(&self, x: f64) -> Option<f64> {
+        Some(self.intercept()? + self.slope()? * x)
+    }
+
+    /// Coefficient of determination of the fitted line.
+    pub fn r_squared(&self) -> Option<f64> {
+        self.correlation().map(|r| r * r)
+    }
+
+    // Sum of squared residuals of the fitted line.
+    fn residual_sum_of_squares(&self) -> Option<f64> {
+        let slope = self.slope()?;
+        Some(f64::max(0.0, self.comoment_yy - slope * self.comoment_xy))
+    }
+
+    /// Standard deviation of the residuals with `n - 2` degrees of freedom.
+    pub fn residual_standard_error(&self) -> Option<f64> {
+        if self.len() < 3 {
+            return None;
+        }
+        let rss = self.residual_sum_of_squares()?;
+        Some(f64::sqrt(rss / (self.len() - 2) as f64))
+    }
+
+    pub fn slope_standard_error(&self) -> Option<f64> {
+        let s = self.residual_standard_error()?;
+        Some(s / f64::sqrt(self.comoment_xx))
+    }
+
+    pub fn intercept_standard_error(&self) -> Option<f64> {
+        let s = self.residual_standard_error()?;
+        let n = self.len() as f64;
+        Some(s * f64::sqrt(1.0 / n + self.mean_x * self.mean_x / self.comoment_xx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+    use super::PairedStats;
+    use crate::error::Error;
+    use crate::test_util::values;
+    use crate::stats::{correlation, covariance, linear_regression, mean, stddev};
+
+    fn check(ps: &PairedStats, xs: &[f64], ys: &[f64]) {
+        assert_eq!(ps.len(), xs.len());
+        assert!( approx_eq!(f64, ps.mean_x().unwrap(), mean(xs).unwrap(), epsilon = 1e-12) );
+        assert!( approx_eq!(f64, ps.mean_y().unwrap(), mean(ys).unwrap(), epsilon = 1e-12) );
+        assert!( approx_eq!(f64, ps.stddev_x().unwrap(), stddev(xs).unwrap(), epsilon = 1e-9) );
+        assert!( approx_eq!(f64, ps.stddev_y().unwrap(), stddev(ys).unwrap(), epsilon = 1e-9) );
+        assert!( approx_eq!(f64, ps.covariance().unwrap(), covariance(xs, ys).unwrap(), epsilon = 1e-9) );
+        assert!( approx_eq!(f64, ps.correlation().unwrap(), correlation(xs, ys).unwrap(), epsilon = 1e-9) );
+    }
+
+    #[test]
+    fn push_remove_test() {
+        let xs = values(100, 1);
+        let ys: Vec<f64> = xs.iter().zip(values(100, 2).iter()).map(|(x, e)| 2.0 * x + e).collect();
+        let mut ps = PairedStats::new();
+        assert_eq!(ps.mean_x(), None);
+        assert_eq!(ps.correlation(), None);
+
+        ps.push_vec(xs.iter().copied().zip(ys.iter().copied()).collect());
+        check(&ps, &xs, &ys);
+        let n = xs.len() as f64;
+        assert!( approx_eq!(f64, ps.sample_covariance().unwrap(), ps.covariance().unwrap() * n / (n - 1.0), epsilon = 1e-12) );
+
+        assert_eq!(ps.remove(10), (xs[10], ys[10]));
+        assert_eq!(ps.pop(), Some((xs[99], ys[99])));
+        ps.trim(5);
+        let (xs, ys) = ([&xs[5..10], &xs[11..99]].concat(), [&ys[5..10], &ys[11..99]].concat());
+        check(&ps, &xs, &ys);
+
+        ps.insert(0, 1.0, 2.0);
+        assert_eq!(ps.get(0), Some((1.0, 2.0)));
+        assert_eq!(ps.iter().count(), ps.len());
+        ps.reset();
+        assert!(ps.is_empty());
+    }
+
+    #[test]
+    fn window_test() {
+        let window = 20;
+        let xs = values(200, 3);
+        let ys: Vec<f64> = xs.iter().zip(values(200, 4).iter()).map(|(x, e)| e - x).collect();
+        let mut ps = PairedStats::with_window(window).unwrap();
+        for (i, (x, y)) in xs.iter().zip(ys.iter()).enumerate() {
+            ps.push(*x, *y);
+            if i >= 1 {
+                let start = (i + 1).saturating_sub(window);
+                check(&ps, &xs[start..=i], &ys[start..=i]);
+            }
+        }
+        assert_eq!(ps.window(), Some(window));
+        assert!(ps.correlation().unwrap() < 0.0);
+    }
+
+    #[test]
+    fn offset_test() {
+        // Raw sums of squares cancel completely at this offset.
+        let xs: Vec<f64> = values(300, 7).iter().map(|x| 1.0e9 + 1.0e3 * x).collect();
+        let ys: Vec<f64> = xs.iter().zip(values(300, 8).iter()).map(|(x, e)| 5.0e8 - 0.5 * x + 100.0 * e).collect();
+        let window = 30;
+        let mut ps = PairedStats::with_window(window).unwrap();
+        for (i, (x, y)) in xs.iter().zip(ys.iter()).enumerate() {
+            ps.push(*x, *y);
+            if i >= 1 {
+                let start = (i + 1).saturating_sub(window);
+                let (xs, ys) = (&xs[start..=i], &ys[start..=i]);
+                assert!( approx_eq!(f64, ps.covariance().unwrap(), covariance(xs, ys).unwrap(), epsilon = 1e-3) );
+                assert!( approx_eq!(f64, ps.correlation().unwrap(), correlation(xs, ys).unwrap(), epsilon = 1e-6) );
+            }
+        }
+    }
+
+    #[test]
+    fn window_parameter_test() {
+        assert_eq!(PairedStats::with_window(0).err(), Some(Error::InvalidParameter("window must be positive")));
+    }
+
+    #[test]
+    fn perfect_correlation_test() {
+        let mut ps = PairedStats::new();
+        ps.push_vec(vec![(1.0, 3.0), (2.0, 5.0), (3.0, 7.0)]);
+        assert!( approx_eq!(f64, ps.correlation().unwrap(), 1.0, epsilon = 1e-15) );
+
+        ps.reset();
+        ps.push_vec(vec![(1.0, 3.0), (2.0, 3.0)]);
+        assert_eq!(ps.correlation(), None);
+        assert_eq!(ps.covariance(), Some(0.0));
+    }
+
+    #[test]
+    fn regression_test() {
+        let xs = values(50, 5);
+        let ys: Vec<f64> = xs.iter().zip(values(50, 6).iter()).map(|(x, e)| 3.0 - 2.0 * x + 0.1 * e).collect();
+        let mut ps = PairedStats::new();
+        assert_eq!(ps.slope(), None);
+        ps.push_vec(xs.iter().copied().zip(ys.iter().copied()).collect());
+
+        let (slope, intercept) = linear_regression(&xs, &ys).unwrap();
+        assert!( approx_eq!(f64, ps.slope().unwrap(), slope, epsilon = 1e-9) );
+        assert!( approx_eq!(f64, ps.intercept().unwrap(), intercept, epsilon = 1e-9) );
+        assert!( approx_eq!(f64, ps.predict(0.5).unwrap(), intercept + 0.5 * slope, epsilon = 1e-9) );
+
+        let residuals: Vec<f64> = xs.iter().zip(ys.iter()).map(|(x, y)| y - intercept - slope * x).collect();
+        let rss: f64 = residuals.iter().map(|r| r * r).sum();
+        let s = f64::sqrt(rss / 48.0);
+        let sxx: f64 = xs.iter().map(|x| (x - mean(&xs).unwrap()).powi(2)).sum();
+        let tss: f64 = ys.iter().map(|y| (y - mean(&ys).unwrap()).powi(2)).sum();
+        assert!( approx_eq!(f64, ps.residual_standard_error().unwrap(), s, epsilon = 1e-9) );
+        assert!( approx_eq!(f64, ps.r_squared().unwrap(), 1.0 - rss / tss, epsilon = 1e-9) );
+        assert!( approx_eq!(f64, ps.slope_standard_error().unwrap(), s / sxx.sqrt(), epsilon = 1e-9) );
+        let mean_x = mean(&xs).unwrap();
+        assert!( approx_eq!(f64, ps.intercept_standard_error().unwrap(),
+            s * f64::sqrt(1.0 / 50.0 + mean_x * mean_x / sxx), epsilon = 1e-9) );
+    }
+
+    #[test]
+    fn rolling_trend_test() {
+        // The trend flips from rising to falling halfway through the stream.
+        let mut ps = PairedStats::with_window(10).unwrap();
+        for t in 0..40 {
+            let y = if t < 20 { t as f64 } else { 40.0 - t as f64 };
+            ps.push(t as f64, y);
+            if t == 19 {
+                assert!( approx_eq!(f64, ps.slope().unwrap(), 1.0, epsilon = 1e-9) );
+                assert!( approx_eq!(f64, ps.residual_standard_error().unwrap(), 0.0, epsilon = 1e-6) );
+            }
+        }
+        assert!( approx_eq!(f64, ps.slope().unwrap(), -1.0, epsilon = 1e-9) );
+        assert!( approx_eq!(f64, ps.intercept().unwrap(), 40.0, epsilon = 1e-9) );
+        assert!( approx_eq!(f64, ps.predict(40.0).unwrap(), 0.0, epsilon = 1e-9) );
+    }
+
+    #[test]
+    fn timestamp_trend_test() {
+        // Timestamps as x leave nothing of n * sum(x²) - sum(x)² in double precision.
+        let mut ps = PairedStats::with_window(10).unwrap();
+        for t in 0..100 {
+            ps.push(1.7e9 + t as f64, 0.5 * t as f64);
+        }
+        assert!( approx_eq!(f64, ps.slope().unwrap(), 0.5, epsilon = 1e-9) );
+        assert!( approx_eq!(f64, ps.correlation().unwrap(), 1.0, epsilon = 1e-9) );
+        assert!( approx_eq!(f64, ps.residual_standard_error().unwrap(), 0.0, epsilon = 1e-6) );
+        assert!( approx_eq!(f64, ps.predict(1.7e9 + 120.0).unwrap(), 60.0, epsilon = 1e-3) );
+        assert!(ps.slope_standard_error().unwrap() < 1e-6);
+    }
+}
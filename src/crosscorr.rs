@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+use std::f64;
+
+use crate::bivariate::PairedStats;
+use crate::error::Error;
+
+/// Windowed cross-correlation between two streams over lags `-max_lag..=max_lag`.
+///
+/// Values of both streams arrive together. At lag L the value of A at time t is paired with
+/// the value of B at time t + L, so a positive best lag means that B follows A. Each lag keeps
+/// its own `PairedStats` over the pairs that fit inside the window, and every push adds one
+/// pair to each of them, so updates are O(max_lag).
+#[derive(Clone, Debug)]
+pub struct CrossCorrelation {
+    window: usize,
+    max_lag: usize,
+    recent: VecDeque<(f64, f64)>,
+    lags: Vec<PairedStats>,
+    length: usize,
+}
+
+impl CrossCorrelation {
+    pub fn new(window: usize, max_lag: usize) -> Result<Self, Error> {
+        if window <= max_lag.saturating_add(1) {
+            return Err(Error::InvalidParameter("window must be longer than max_lag + 1"));
+        }
+        let lags = (0..=2 * max_lag)
+            .map(|i| PairedStats::with_window(window - i.abs_diff(max_lag)))
+            .collect::<Result<_, _>>()?;
+        Ok(CrossCorrelation {
+            window,
+            max_lag,
+            recent: VecDeque::with_capacity(max_lag + 1),
+            lags,
+            length: 0,
+        })
+    }
+
+    pub fn push(&mut self, a: f64, b: f64) {
+        self.recent.push_back((a, b));
+        if self.recent.len() > self.max_lag + 1 {
+            self.recent.pop_front();
+        }
+        for (lag, (earlier_a, earlier_b)) in self.recent.iter().rev().enumerate() {
+            self.lags[self.max_lag + lag].push(*earlier_a, b);
+            if lag > 0 {
+                self.lags[self.max_lag - lag].push(a, *earlier_b);
+            }
+        }
+        self.length = usize::min(self.length + 1, self.window);
+    }
+
+    pub fn push_vec(&mut self, v: Vec<(f64, f64)>) {
+        v.iter().for_each(|(a, b)| self.push(*a, *b));
+    }
+
+    pub fn reset(&mut self) {
+        self.recent.clear();
+        self.lags.iter_mut().for_each(|l| l.reset());
+        self.length = 0;
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    pub fn max_lag(&self) -> usize {
+        self.max_lag
+    }
+
+    /// Number of time steps currently in the window.
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Pearson correlation between A at time t and B at time t + lag within the window.
+    pub fn correlation(&self, lag: isize) -> Option<f64> {
+        if lag.unsigned_abs() > self.max_lag {
+            return None;
+        }
+        self.lags[(self.max_lag as isize + lag) as usize].correlation()
+    }
+
+    /// Correlations for every lag from `-max_lag` to `max_lag`.
+    pub fn correlations(&self) -> Vec<(isize, Option<f64>)> {
+        let max_lag = self.max_lag as isize;
+        (-max_lag..=max_lag).map(|lag| (lag, self.correlation(lag))).collect()
+    }
+
+    /// Lag with the strongest correlation in absolute value, together with that correlation.
+    /// Ties go to the lag closest to zero.
+    pub fn best_lag(&self) -> Option<(isize, f64)> {
+        let mut best: Option<(isize, f64)> = None;
+        for (lag, r) in self.correlations() {
+            if let Some(r) = r {
+                let better = best.is_none_or(|(best_lag, best_r)| {
+                    r.abs() > best_r.abs() || (r.abs() == best_r.abs() && lag.abs() < best_lag.abs())
+                });
+                if better {
+                    best = Some((lag, r));
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+    use super::CrossCorrelation;
+    use crate::error::Error;
+    use crate::test_util::values;
+    use crate::stats::cross_correlation;
+
+    // B repeats A `delay` steps later with some noise.
+    fn delayed(n: usize, delay: usize, seed: u64) -> (Vec<f64>, Vec<f64>) {
+        let a = values(n + delay, seed);
+        let noise = values(n, seed + 1);
+        let b = (0..n).map(|t| a[t] + 0.3 * noise[t]).collect();
+        (a[delay..].to_vec(), b)
+    }
+
+    #[test]
+    fn best_lag_test() {
+        let (a, b) = delayed(300, 4, 1);
+        let mut cc = CrossCorrelation::new(100, 8).unwrap();
+        assert_eq!(cc.best_lag(), None);
+        cc.push_vec(a.iter().copied().zip(b.iter().copied()).collect());
+
+        // a[t] = b[t + 4] up to noise, so B follows A by four steps.
+        let (lag, r) = cc.best_lag().unwrap();
+        assert_eq!(lag, 4);
+        assert!(r > 0.9);
+        assert_eq!(cc.correlations().len(), 17);
+        assert_eq!(cc.correlation(9), None);
+
+        let mut reversed = CrossCorrelation::new(100, 8).unwrap();
+        reversed.push_vec(b.iter().copied().zip(a.iter().copied()).collect());
+        assert_eq!(reversed.best_lag().unwrap().0, -4);
+    }
+
+    #[test]
+    fn window_test() {
+        let window = 50;
+        let a = values(150, 3);
+        let b = values(150, 4);
+        let mut cc = CrossCorrelation::new(window, 5).unwrap();
+        for t in 0..a.len() {
+            cc.push(a[t], b[t]);
+            if t >= 10 {
+                let start = (t + 1).saturating_sub(window);
+                for lag in -5..=5 {
+                    let expected = cross_correlation(&a[start..=t], &b[start..=t], lag).unwrap();
+                    assert!( approx_eq!(f64, cc.correlation(lag).unwrap(), expected, epsilon = 1e-9) );
+                }
+            }
+        }
+        assert_eq!(cc.len(), window);
+
+        cc.reset();
+        assert!(cc.is_empty());
+        assert_eq!(cc.correlation(0), None);
+    }
+
+    #[test]
+    fn offset_test() {
+        // Both streams sit on top of a large constant, as with counters or timestamps, which
+        // leaves about seven significant digits for the deviations.
+        let (a, b) = delayed(200, 3, 5);
+        let a: Vec<f64> = a.iter().map(|a| 1.0e9 + a).collect();
+        let b: Vec<f64> = b.iter().map(|b| 2.0e9 - b).collect();
+        let mut cc = CrossCorrelation::new(60, 5).unwrap();
+        cc.push_vec(a.iter().copied().zip(b.iter().copied()).collect());
+
+        let (lag, r) = cc.best_lag().unwrap();
+        assert_eq!(lag, 3);
+        assert!(r < -0.9);
+        for lag in -5..=5 {
+            let expected = cross_correlation(&a[140..], &b[140..], lag).unwrap();
+            assert!( approx_eq!(f64, cc.correlation(lag).unwrap(), expected, epsilon = 1e-5) );
+        }
+    }
+
+    #[test]
+    fn parameter_test() {
+        assert!(matches!(CrossCorrelation::new(5, 4), Err(Error::InvalidParameter(_))));
+        assert!(matches!(CrossCorrelation::new(10, usize::MAX), Err(Error::InvalidParameter(_))));
+        assert!(CrossCorrelation::new(6, 4).is_ok());
+    }
+}
@@ -0,0 +1,246 @@
+use std::f64;
+
+use crate::error::Error;
+
+// Largest exponent alpha * (t - landmark) allowed before the landmark is moved forward.
+const MAX_EXPONENT: f64 = 300.0;
+
+/// Decay function g applied to the age of an observation relative to the landmark.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decay {
+    /// g(n) = exp(alpha * n)
+    Exponential(f64),
+    /// g(n) = n^beta
+    Polynomial(f64),
+}
+
+impl Decay {
+    fn check(&self) -> Result<(), Error> {
+        match *self {
+            Decay::Exponential(alpha) if alpha.is_finite() && alpha > 0.0 => Ok(()),
+            Decay::Polynomial(beta) if beta.is_finite() && beta > 0.0 => Ok(()),
+            _ => Err(Error::InvalidParameter("decay rate must be finite and positive")),
+        }
+    }
+
+    fn weight(&self, age: f64) -> f64 {
+        match *self {
+            Decay::Exponential(alpha) => f64::exp(alpha * age),
+            Decay::Polynomial(beta) => f64::powf(f64::max(0.0, age), beta),
+        }
+    }
+}
+
+/// Forward-decayed statistics over a stream of timestamped values.
+///
+/// Each value pushed at time `t_i` carries the static weight `g(t_i - L)` where `L` is the
+/// landmark, so nothing stored needs to be touched as time advances. Queries at time `t`
+/// divide by `g(t - L)`. Timestamps are expected to be no earlier than the landmark, and with
+/// polynomial decay a value pushed at the landmark itself has zero weight.
+#[derive(Clone, Debug)]
+pub struct ForwardDecay {
+    decay: Decay,
+    landmark: f64,
+    length: usize,
+    weight: f64,
+    weighted_sum: f64,
+    weighted_sum_of_squares: f64,
+}
+
+impl ForwardDecay {
+    /// Creates an empty accumulator. The decay rate must be finite and positive and the
+    /// landmark finite.
+    pub fn new(decay: Decay, landmark: f64) -> Result<Self, Error> {
+        decay.check()?;
+        if !landmark.is_finite() {
+            return Err(Error::InvalidParameter("landmark must be finite"));
+        }
+        Ok(ForwardDecay {
+            decay,
+            landmark,
+            length: 0,
+            weight: 0.0,
+            weighted_sum: 0.0,
+            weighted_sum_of_squares: 0.0,
+        })
+    }
+
+    pub fn exponential(alpha: f64, landmark: f64) -> Result<Self, Error> {
+        Self::new(Decay::Exponential(alpha), landmark)
+    }
+
+    pub fn polynomial(beta: f64, landmark: f64) -> Result<Self, Error> {
+        Self::new(Decay::Polynomial(beta), landmark)
+    }
+
+    // g(t - L), or None when it vanishes so that nothing can be scaled to time t.
+    fn scale(&self, t: f64) -> Option<f64> {
+        let g = self.decay.weight(t - self.landmark);
+        if self.length > 0 && g > 0.0 {
+            Some(g)
+        } else {
+            None
+        }
+    }
+
+    pub fn push(&mut self, x: f64, t: f64) {
+        if let Decay::Exponential(alpha) = self.decay {
+            if alpha * (t - self.landmark) > MAX_EXPONENT {
+                self.renormalize(t);
+            }
+        }
+        let g = self.decay.weight(t - self.landmark);
+        self.weight += g;
+        self.weighted_sum += g * x;
+        self.weighted_sum_of_squares += g * x * x;
+        self.length += 1;
+    }
+
+    /// Moves the landmark forward, rescaling the cached sums so that all queries are unchanged.
+    ///
+    /// Only exponential decay can be renormalized exactly; for polynomial decay the weights
+    /// grow slowly enough that overflow is not a concern and this is a no-op.
+    pub fn renormalize(&mut self, landmark: f64) {
+        if let Decay::Exponential(alpha) = self.decay {
+            let scale = f64::exp(alpha * (self.landmark - landmark));
+            self.weight *= scale;
+            self.weighted_sum *= scale;
+            self.weighted_sum_of_squares *= scale;
+            self.landmark = landmark;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.length = 0;
+        self.weight = 0.0;
+        self.weighted_sum = 0.0;
+        self.weighted_sum_of_squares = 0.0;
+    }
+
+    pub fn landmark(&self) -> f64 {
+        self.landmark
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn count(&self, t: f64) -> Option<f64> {
+        self.scale(t).map(|g| self.weight / g)
+    }
+
+    pub fn sum(&self, t: f64) -> Option<f64> {
+        self.scale(t).map(|g| self.weighted_sum / g)
+    }
+
+    // The query time cancels out of the ratio, so the decayed mean and variance only
+    // depend on the relative weights of the stored observations.
+    pub fn mean(&self) -> Option<f64> {
+        if self.length > 0 && self.weight > 0.0 {
+            Some(self.weighted_sum / self.weight)
+        } else {
+            None
+        }
+    }
+
+    pub fn variance(&self) -> Option<f64> {
+        self.mean().map(|mean| {
+            f64::max(0.0, self.weighted_sum_of_squares / self.weight - mean * mean)
+        })
+    }
+
+    pub fn stddev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+    use super::{Decay, ForwardDecay};
+
+    #[test]
+    fn exponential_count_test() {
+        let alpha = 0.5;
+        let mut fd = ForwardDecay::exponential(alpha, 0.0).unwrap();
+        fd.push(1.0, 1.0);
+        fd.push(3.0, 2.0);
+
+        let t = 4.0;
+        let expected = f64::exp(-alpha * 3.0) + f64::exp(-alpha * 2.0);
+        assert!( approx_eq!(f64, fd.count(t).unwrap(), expected, epsilon = 1e-12) );
+        let expected = f64::exp(-alpha * 3.0) + 3.0 * f64::exp(-alpha * 2.0);
+        assert!( approx_eq!(f64, fd.sum(t).unwrap(), expected, epsilon = 1e-12) );
+    }
+
+    #[test]
+    fn polynomial_mean_variance_test() {
+        let mut fd = ForwardDecay::polynomial(2.0, 0.0).unwrap();
+        fd.push(2.0, 1.0);
+        fd.push(4.0, 2.0);
+
+        let (w1, w2) = (1.0, 4.0);
+        let mean = (w1 * 2.0 + w2 * 4.0) / (w1 + w2);
+        let variance = (w1 * 4.0 + w2 * 16.0) / (w1 + w2) - mean * mean;
+        assert!( approx_eq!(f64, fd.mean().unwrap(), mean, epsilon = 1e-12) );
+        assert!( approx_eq!(f64, fd.variance().unwrap(), variance, epsilon = 1e-12) );
+        assert!( approx_eq!(f64, fd.count(2.0).unwrap(), (w1 + w2) / 4.0, epsilon = 1e-12) );
+    }
+
+    #[test]
+    fn renormalize_test() {
+        let mut fd = ForwardDecay::exponential(1.0, 0.0).unwrap();
+        let mut rf = ForwardDecay::exponential(1.0, 0.0).unwrap();
+        for i in 0..10 {
+            fd.push(i as f64, i as f64);
+            rf.push(i as f64, i as f64);
+        }
+        rf.renormalize(8.0);
+
+        assert_eq!(rf.landmark(), 8.0);
+        assert!( approx_eq!(f64, fd.count(12.0).unwrap(), rf.count(12.0).unwrap(), epsilon = 1e-12) );
+        assert!( approx_eq!(f64, fd.mean().unwrap(), rf.mean().unwrap(), epsilon = 1e-12) );
+    }
+
+    #[test]
+    fn overflow_test() {
+        let mut fd = ForwardDecay::exponential(1.0, 0.0).unwrap();
+        for i in 0..5000 {
+            fd.push(1.0, i as f64);
+        }
+
+        assert!(fd.count(5000.0).unwrap().is_finite());
+        assert!( approx_eq!(f64, fd.mean().unwrap(), 1.0, epsilon = 1e-12) );
+        assert!( approx_eq!(f64, fd.count(4999.0).unwrap(), 1.0 / (1.0 - f64::exp(-1.0)), epsilon = 1e-9) );
+    }
+
+    #[test]
+    fn parameter_test() {
+        assert!(ForwardDecay::exponential(-0.5, 0.0).is_err());
+        assert!(ForwardDecay::exponential(f64::NAN, 0.0).is_err());
+        assert!(ForwardDecay::polynomial(0.0, 0.0).is_err());
+        assert!(ForwardDecay::polynomial(f64::INFINITY, 0.0).is_err());
+        assert!(ForwardDecay::new(Decay::Exponential(1.0), f64::NAN).is_err());
+
+        // Polynomial weights vanish at the landmark, which leaves nothing to divide by.
+        let mut fd = ForwardDecay::polynomial(1.0, 0.0).unwrap();
+        fd.push(3.0, 0.0);
+        assert_eq!(fd.count(0.0), None);
+        assert_eq!(fd.mean(), None);
+        fd.push(5.0, 2.0);
+        assert_eq!(fd.mean(), Some(5.0));
+    }
+
+    #[test]
+    fn empty_test() {
+        let fd = ForwardDecay::exponential(1.0, 0.0).unwrap();
+
+        assert_eq!(fd.count(1.0), None);
+        assert_eq!(fd.mean(), None);
+        assert_eq!(fd.stddev(), None);
+    }
+}
@@ -0,0 +1,31 @@
+use std::error;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// A constructor argument is outside the range the structure supports.
+    InvalidParameter(&'static str),
+    /// A value cannot be recorded because it lies outside the trackable range.
+    OutOfRange,
+    /// A byte encoding is truncated or malformed.
+    InvalidEncoding,
+    /// A statistic was requested before any value was recorded.
+    Empty,
+    /// A statistic that is only defined for positive values was requested while some recorded
+    /// value is zero, negative or NaN.
+    NonPositive,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidParameter(msg) => write!(f, "invalid parameter: {}", msg),
+            Error::OutOfRange => write!(f, "value out of trackable range"),
+            Error::InvalidEncoding => write!(f, "invalid encoding"),
+            Error::Empty => write!(f, "no values recorded"),
+            Error::NonPositive => write!(f, "statistic requires positive values"),
+        }
+    }
+}
+
+impl error::Error for Error {}
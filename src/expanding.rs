@@ -0,0 +1,29 @@
+/// Expanding-window statistics of a series, one entry per step.
+///
+/// Entry `i` of each column holds the statistic over every value up to and including step `i`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Expanding<T> {
+    pub mean: Vec<T>,
+    pub stddev: Vec<T>,
+    pub min: Vec<T>,
+    pub max: Vec<T>,
+}
+
+impl<T> Expanding<T> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Expanding {
+            mean: Vec::with_capacity(capacity),
+            stddev: Vec::with_capacity(capacity),
+            min: Vec::with_capacity(capacity),
+            max: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mean.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.mean.len()
+    }
+}
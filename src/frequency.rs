@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+
+use num_traits::Float;
+
+use crate::error::Error;
+use crate::order_tree::total_cmp;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Key {
+    // Index of the bin when `bin_width` is set, or the bits of the value otherwise
+    Finite(i64),
+    NegativeInfinity,
+    Infinity,
+    NaN,
+}
+
+// Counts of each distinct value, or of each bin of `bin_width` when set, together with the
+// keys at every count. Each key remembers its slot in the list for its count, so moving it up
+// or down a level is a swap removal and the highest count and one of its keys survive
+// removals in O(1). Infinities and NaN keep their own keys, and values whose bin index does
+// not fit in an i64 are only counted so that the queries can report them.
+#[derive(Clone, Debug)]
+pub(crate) struct FrequencyTable<T> {
+    bin_width: Option<T>,
+    // Count of each key and its slot in `levels[count]`
+    counts: HashMap<Key, (usize, usize)>,
+    levels: Vec<Vec<Key>>,
+    max_count: usize,
+    out_of_range: usize,
+}
+
+impl<T: Float> FrequencyTable<T> {
+    pub(crate) fn new(bin_width: Option<T>) -> Self {
+        FrequencyTable {
+            bin_width,
+            counts: HashMap::new(),
+            levels: vec![vec![]],
+            max_count: 0,
+            out_of_range: 0,
+        }
+    }
+
+    pub(crate) fn from_values(bin_width: Option<T>, values: &[T]) -> Self {
+        let mut table = FrequencyTable::new(bin_width);
+        values.iter().for_each(|x| table.insert(*x));
+        table
+    }
+
+    // None when the bin index overflows an i64.
+    fn key(&self, x: T) -> Option<Key> {
+        if x.is_nan() {
+            return Some(Key::NaN);
+        }
+        if x.is_infinite() {
+            return Some(if x > T::zero() { Key::Infinity } else { Key::NegativeInfinity });
+        }
+        match self.bin_width {
+            Some(width) => (x / width).floor().to_i64().map(Key::Finite),
+            // Adding zero folds -0.0 into 0.0
+            None => Some(Key::Finite((x + T::zero()).to_f64().unwrap().to_bits() as i64)),
+        }
+    }
+
+    fn value(&self, key: Key) -> T {
+        match key {
+            Key::Finite(k) => match self.bin_width {
+                Some(width) => T::from(k).unwrap() * width,
+                None => T::from(f64::from_bits(k as u64)).unwrap(),
+            },
+            Key::NegativeInfinity => T::neg_infinity(),
+            Key::Infinity => T::infinity(),
+            Key::NaN => T::nan(),
+        }
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        if self.out_of_range > 0 {
+            Err(Error::OutOfRange)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Takes the key out of its level, moving the last key of that level into its slot.
+    fn unlink(&mut self, count: usize, slot: usize) {
+        self.levels[count].swap_remove(slot);
+        if let Some(moved) = self.levels[count].get(slot) {
+            self.counts.get_mut(moved).unwrap().1 = slot;
+        }
+    }
+
+    fn link(&mut self, key: Key, count: usize) {
+        if count == self.levels.len() {
+            self.levels.push(vec![]);
+        }
+        self.counts.insert(key, (count, self.levels[count].len()));
+        self.levels[count].push(key);
+    }
+
+    pub(crate) fn insert(&mut self, x: T) {
+        let key = match self.key(x) {
+            Some(key) => key,
+            None => {
+                self.out_of_range += 1;
+                return;
+            }
+        };
+        let count = match self.counts.get(&key) {
+            Some(&(count, slot)) => {
+                self.unlink(count, slot);
+                count
+            }
+            None => 0,
+        };
+        self.link(key, count + 1);
+        self.max_count = usize::max(self.max_count, count + 1);
+    }
+
+    pub(crate) fn remove(&mut self, x: T) {
+        let key = match self.key(x) {
+            Some(key) => key,
+            None => {
+                self.out_of_range -= 1;
+                return;
+            }
+        };
+        if let Some(&(count, slot)) = self.counts.get(&key) {
+            self.unlink(count, slot);
+            if self.max_count == count && self.levels[count].is_empty() {
+                self.max_count -= 1;
+            }
+            if count > 1 {
+                self.link(key, count - 1);
+            } else {
+                self.counts.remove(&key);
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.counts.clear();
+        self.levels = vec![vec![]];
+        self.max_count = 0;
+        self.out_of_range = 0;
+    }
+
+    pub(crate) fn distinct(&self) -> Result<usize, Error> {
+        self.check()?;
+        Ok(self.counts.len())
+    }
+
+    pub(crate) fn frequency(&self, x: T) -> Result<usize, Error> {
+        self.check()?;
+        let key = self.key(x).ok_or(Error::OutOfRange)?;
+        Ok(self.counts.get(&key).map_or(0, |(count, _)| *count))
+    }
+
+    // One of the most frequent values, without looking at the others.
+    pub(crate) fn mode(&self) -> Result<T, Error> {
+        self.check()?;
+        self.levels[self.max_count].last()
+            .map(|key| self.value(*key))
+            .ok_or(Error::Empty)
+    }
+
+    pub(crate) fn modes(&self) -> Result<Vec<T>, Error> {
+        self.check()?;
+        let mut modes: Vec<T> = self.levels[self.max_count].iter()
+            .map(|key| self.value(*key))
+            .collect();
+        modes.sort_by(total_cmp);
+        Ok(modes)
+    }
+
+    pub(crate) fn frequencies(&self) -> Result<Vec<(T, usize)>, Error> {
+        self.check()?;
+        let mut frequencies: Vec<(T, usize)> = self.counts.iter()
+            .map(|(key, (count, _))| (self.value(*key), *count))
+            .collect();
+        frequencies.sort_by(|a, b| total_cmp(&a.0, &b.0));
+        Ok(frequencies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrequencyTable;
+    use crate::error::Error;
+
+    #[test]
+    fn insert_remove_test() {
+        let mut table = FrequencyTable::new(None);
+        [2.0, 1.0, 2.0, -0.0, 0.0, 1.0].iter().for_each(|x| table.insert(*x));
+
+        assert_eq!(table.distinct(), Ok(3));
+        assert_eq!(table.modes(), Ok(vec![0.0, 1.0, 2.0]));
+        assert_eq!(table.frequency(0.0), Ok(2));
+
+        table.remove(1.0);
+        table.remove(0.0);
+        assert_eq!(table.modes(), Ok(vec![2.0]));
+        assert_eq!(table.mode(), Ok(2.0));
+        table.remove(2.0);
+        assert_eq!(table.modes(), Ok(vec![0.0, 1.0, 2.0]));
+        assert_eq!(table.frequencies(), Ok(vec![(0.0, 1), (1.0, 1), (2.0, 1)]));
+    }
+
+    #[test]
+    fn mode_churn_test() {
+        // The mode always has the highest count while values come and go.
+        let vs: Vec<f64> = (0..400).map(|i| ((i * 7919) % 13) as f64).collect();
+        let mut table = FrequencyTable::new(None);
+        for (i, x) in vs.iter().enumerate() {
+            table.insert(*x);
+            if i >= 50 {
+                table.remove(vs[i - 50]);
+            }
+            let highest = table.frequencies().unwrap().iter().map(|(_, c)| *c).max().unwrap();
+            assert_eq!(table.frequency(table.mode().unwrap()), Ok(highest));
+            assert!(table.modes().unwrap().contains(&table.mode().unwrap()));
+        }
+    }
+
+    #[test]
+    fn bin_width_test() {
+        let table = FrequencyTable::from_values(Some(0.5), &[0.1, 0.2, 0.7, 1.1, 1.4, 1.3, -0.1]);
+
+        assert_eq!(table.modes(), Ok(vec![1.0]));
+        assert_eq!(table.frequencies(), Ok(vec![(-0.5, 1), (0.0, 2), (0.5, 1), (1.0, 3)]));
+    }
+
+    #[test]
+    fn non_finite_test() {
+        for width in [None, Some(1.0)].iter() {
+            let mut table = FrequencyTable::from_values(*width, &[f64::NAN, f64::INFINITY, 1.0, f64::NEG_INFINITY, f64::NAN]);
+
+            assert_eq!(table.distinct(), Ok(4));
+            assert!(table.mode().unwrap().is_nan());
+            assert_eq!(table.frequency(f64::INFINITY), Ok(1));
+            let frequencies = table.frequencies().unwrap();
+            assert_eq!(&frequencies[..3], &[(f64::NEG_INFINITY, 1), (1.0, 1), (f64::INFINITY, 1)]);
+            assert!(frequencies[3].0.is_nan());
+            assert_eq!(frequencies[3].1, 2);
+
+            table.remove(f64::NAN);
+            table.remove(f64::NAN);
+            assert_eq!(table.frequency(f64::NAN), Ok(0));
+            assert_eq!(table.distinct(), Ok(3));
+        }
+    }
+
+    #[test]
+    fn out_of_range_test() {
+        let mut table = FrequencyTable::from_values(Some(1.0), &[1e300, 2.0]);
+        assert_eq!(table.distinct(), Err(Error::OutOfRange));
+        assert_eq!(table.mode(), Err(Error::OutOfRange));
+
+        table.remove(1e300);
+        assert_eq!(table.distinct(), Ok(1));
+        assert_eq!(table.frequency(-1e300), Err(Error::OutOfRange));
+        table.remove(2.0);
+        assert_eq!(table.mode(), Err(Error::Empty));
+    }
+}
@@ -1,16 +1,36 @@
 use num_traits::{Float, AsPrimitive};
-use std::ops::{Bound, RangeBounds};
+use std::borrow::Cow;
+use std::iter::FromIterator;
+use std::ops::{Bound, Index, RangeBounds};
+use std::slice::{self, SliceIndex};
+use std::vec;
 use std::default::Default;
 use std::ops::{AddAssign, SubAssign};
 
-#[derive(Default, Debug)]
+use crate::error::Error;
+use crate::expanding::Expanding;
+use crate::frequency::FrequencyTable;
+use crate::moments;
+use crate::order_tree::{total_cmp, OrderTree};
+
+// Ratio of the standard deviation to the MAD of a normal distribution
+const MAD_NORMAL: f64 = 1.482_602_218_505_602;
+
+#[derive(Clone, Default, Debug)]
 pub struct Stats<T: Float + Default + AddAssign + SubAssign + AsPrimitive<T>> {
-    pub data: Vec<T>,
+    data: Vec<T>,
     length: usize,
     sum: T,
     sum_of_squares: T,
+    log_sum: T,
+    reciprocal_sum: T,
+    non_positive: usize,
+    non_finite: usize,
     max: Option<T>,
     min: Option<T>,
+    order: Option<OrderTree<T>>,
+    bin_width: Option<T>,
+    frequency: Option<FrequencyTable<T>>,
 }
 
 impl<T: Float + Default + AddAssign + SubAssign + AsPrimitive<T>> Stats<T> 
@@ -20,9 +40,102 @@ where usize: AsPrimitive<T>
         Default::default()
     }
 
+    /// Keeps an ordered index of the values up to date on every change, so that the value range
+    /// and robust statistics take O(log n) instead of sorting the data on each call. Every push
+    /// and removal then costs O(log n) as well.
+    pub fn with_order_index(mut self) -> Self {
+        self.order = Some(OrderTree::from_values(&self.data));
+        self
+    }
+
+    /// Keeps a frequency table of the values up to date on every change, so that `mode`
+    /// takes O(1) and the other frequency queries avoid counting the data on each call.
+    pub fn with_frequency_table(mut self) -> Self {
+        self.frequency = Some(FrequencyTable::from_values(self.bin_width, &self.data));
+        self
+    }
+
+    fn index(&mut self, x: T) {
+        if let Some(order) = self.order.as_mut() {
+            order.insert(x);
+        }
+        if let Some(frequency) = self.frequency.as_mut() {
+            frequency.insert(x);
+        }
+    }
+
+    fn unindex(&mut self, x: T) {
+        if let Some(order) = self.order.as_mut() {
+            let found = order.remove(x);
+            debug_assert!(found, "value missing from the order index");
+        }
+        if let Some(frequency) = self.frequency.as_mut() {
+            frequency.remove(x);
+        }
+    }
+
+    // The maintained order index, or one built from the data for a single query.
+    fn order(&self) -> Cow<'_, OrderTree<T>> {
+        match &self.order {
+            Some(order) => Cow::Borrowed(order),
+            None => Cow::Owned(OrderTree::from_values(&self.data)),
+        }
+    }
+
+    // The maintained frequency table, or one counted from the data for a single query.
+    fn frequency_table(&self) -> Cow<'_, FrequencyTable<T>> {
+        match &self.frequency {
+            Some(frequency) => Cow::Borrowed(frequency),
+            None => Cow::Owned(FrequencyTable::from_values(self.bin_width, &self.data)),
+        }
+    }
+
+    // Positive values enter the running sums of logarithms and reciprocals, except infinities
+    // and values too small for a finite reciprocal, which are only counted so that removing
+    // them later cannot leave inf - inf behind.
+    fn add_log_cache(&mut self, x: T) {
+        if x > T::zero() {
+            let (log, reciprocal) = (x.ln(), x.recip());
+            if log.is_finite() && reciprocal.is_finite() {
+                self.log_sum += log;
+                self.reciprocal_sum += reciprocal;
+            } else {
+                self.non_finite += 1;
+            }
+        } else {
+            self.non_positive += 1;
+        }
+    }
+
+    fn del_log_cache(&mut self, x: T) {
+        if x > T::zero() {
+            let (log, reciprocal) = (x.ln(), x.recip());
+            if log.is_finite() && reciprocal.is_finite() {
+                self.log_sum -= log;
+                self.reciprocal_sum -= reciprocal;
+            } else {
+                self.non_finite -= 1;
+            }
+        } else {
+            self.non_positive -= 1;
+        }
+    }
+
+    // The running sums of logarithms and reciprocals, or a pass over the data while they leave
+    // out a value.
+    fn log_reciprocal_sums(&self) -> (T, T) {
+        if self.non_finite == 0 {
+            (self.log_sum, self.reciprocal_sum)
+        } else {
+            self.data.iter().fold((T::zero(), T::zero()), |(log, reciprocal), x| (log + x.ln(), reciprocal + x.recip()))
+        }
+    }
+
     fn add_cache(&mut self, x: T) {
+        self.index(x);
         self.sum += x;
         self.sum_of_squares += x * x;
+        self.add_log_cache(x);
         self.length += 1;
 
         if self.max.is_none() || self.max < Some(x) {
@@ -34,8 +147,10 @@ where usize: AsPrimitive<T>
     }
 
     fn del_cache(&mut self, x: T) {
+        self.unindex(x);
         self.sum -= x;
         self.sum_of_squares -= x * x;
+        self.del_log_cache(x);
         self.length -= 1;
         
         if self.length == 0 {
@@ -59,30 +174,172 @@ where usize: AsPrimitive<T>
         }
     }
 
+    fn replace_cache(&mut self, old: T, new: T) {
+        self.unindex(old);
+        self.index(new);
+        self.sum += new - old;
+        self.sum_of_squares += new * new - old * old;
+        self.del_log_cache(old);
+        self.add_log_cache(new);
+
+        if (self.max == Some(old) && new < old) || (self.min == Some(old) && new > old) {
+            self.rescan_extremes();
+        } else {
+            if self.max < Some(new) {
+                self.max = Some(new)
+            };
+            if self.min > Some(new) {
+                self.min = Some(new)
+            };
+        }
+    }
+
+    fn remove_cache(&mut self, removed: &[T]) {
+        let mut extreme = false;
+        for x in removed.iter() {
+            self.unindex(*x);
+            self.sum -= *x;
+            self.sum_of_squares -= *x * *x;
+            self.del_log_cache(*x);
+            self.length -= 1;
+            extreme |= self.max == Some(*x) || self.min == Some(*x);
+        }
+        if extreme {
+            self.rescan_extremes();
+        }
+    }
+
+    fn rescan_extremes(&mut self) {
+        self.max = None;
+        self.min = None;
+        for x in self.data.iter() {
+            if self.max.is_none() || self.max < Some(*x) {
+                self.max = Some(*x)
+            };
+            if self.min.is_none() || self.min > Some(*x) {
+                self.min = Some(*x)
+            };
+        }
+    }
+
+    fn value_range<R>(&self, range: &R) -> (usize, T)
+    where
+        R: RangeBounds<T>,
+    {
+        use Bound::*;
+        let order = self.order();
+        let (count_low, sum_low) = match range.start_bound() {
+            Unbounded => (0, 0.as_()),
+            Included(s) => order.rank(*s, false),
+            Excluded(s) => order.rank(*s, true),
+        };
+        let (count_high, sum_high) = match range.end_bound() {
+            Unbounded => (self.length, order.prefix(self.length).0),
+            Included(e) => order.rank(*e, true),
+            Excluded(e) => order.rank(*e, false),
+        };
+        if count_high > count_low {
+            (count_high - count_low, sum_high - sum_low)
+        } else {
+            (0, 0.as_())
+        }
+    }
+
+    // The k-th smallest absolute deviation from the median. The deviations of the values below
+    // the median and of the rest form two sorted sequences, so this is a search for the k-th
+    // smallest element of their union, reading each element from the order tree.
+    fn kth_deviation(&self, order: &OrderTree<T>, median: T, k: usize) -> T {
+        let below = order.rank(median, false).0;
+        let above = self.length - below;
+        let left = |i: usize| median - order.select(below - 1 - i).unwrap();
+        let right = |j: usize| order.select(below + j).unwrap() - median;
+        let (mut lo, mut hi) = ((k + 1).saturating_sub(above), usize::min(k + 1, below));
+        while lo < hi {
+            let i = (lo + hi) / 2;
+            if right(k - i) > left(i) {
+                lo = i + 1;
+            } else {
+                hi = i;
+            }
+        }
+        let j = k + 1 - lo;
+        match (lo > 0, j > 0) {
+            (true, true) => T::max(left(lo - 1), right(j - 1)),
+            (true, false) => left(lo - 1),
+            _ => right(j - 1),
+        }
+    }
+
+    // Number of values trimmed from each end for a proportion in [0, 0.5).
+    fn trimmed_count(&self, proportion: T) -> Option<usize> {
+        if self.length == 0 || proportion.is_nan() || proportion < T::zero() || proportion >= T::from(0.5).unwrap() {
+            None
+        } else {
+            Some((proportion * self.length.as_()).floor().to_usize().unwrap())
+        }
+    }
+
+    // Sum and sum of squares after replacing the g smallest and g largest values by their
+    // nearest remaining neighbours.
+    fn winsorized_sums(&self, g: usize) -> (T, T) {
+        let order = self.order();
+        let (low_sum, low_squares) = order.prefix(g);
+        let (high_sum, high_squares) = order.prefix(self.length - g);
+        let low = order.select(g).unwrap();
+        let high = order.select(self.length - 1 - g).unwrap();
+        let count = g.as_();
+        (
+            high_sum - low_sum + count * (low + high),
+            high_squares - low_squares + count * (low * low + high * high),
+        )
+    }
+
+    fn quantile_of(&self, order: &OrderTree<T>, q: T) -> Option<T> {
+        if self.length == 0 || q.is_nan() || q < T::zero() || q > T::one() {
+            return None;
+        }
+        let index = q * (self.length - 1).as_();
+        let lower = index.floor();
+        let low = order.select(lower.to_usize().unwrap())?;
+        let high = order.select(index.ceil().to_usize().unwrap())?;
+        Some(low + (high - low) * (index - lower))
+    }
+
+    fn check_positive(&self) -> Result<(), Error> {
+        if self.length == 0 {
+            Err(Error::Empty)
+        } else if self.non_positive > 0 {
+            Err(Error::NonPositive)
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn reset(&mut self) {
         self.data = vec![];
         self.length = 0;
         self.sum = 0.as_();
         self.sum_of_squares = 0.as_();
+        self.log_sum = 0.as_();
+        self.reciprocal_sum = 0.as_();
+        self.non_positive = 0;
+        self.non_finite = 0;
         self.max = None;
         self.min = None;
+        if let Some(order) = self.order.as_mut() {
+            order.clear();
+        }
+        if let Some(frequency) = self.frequency.as_mut() {
+            frequency.clear();
+        }
     }
 
     pub fn mean(&mut self) -> Option<T> {
-        if self.length > 0 {
-            Some(self.sum / self.length.as_())
-        } else {
-            None
-        }
+        moments::mean(self.length, self.sum)
     }
 
     pub fn stddev(&mut self) -> Option<T> {
-        if self.length > 0 {
-            Some(T::sqrt(T::max(0.as_(), 
-                self.length.as_() * self.sum_of_squares - self.sum * self.sum) / (self.length.as_() * self.length.as_())))
-        } else {
-            None
-        }
+        moments::variance(self.length, self.sum, self.sum_of_squares).map(T::sqrt)
     }
 
     pub fn min(&self) -> Option<T> {
@@ -125,8 +382,32 @@ where usize: AsPrimitive<T>
         [start, end - start + 1]    
     }
 
-    pub fn data(&mut self) -> Vec<T> {
-        self.data.to_vec()
+    pub fn count_in_value_range<R>(&self, range: &R) -> usize
+    where
+        R: RangeBounds<T>,
+    {
+        self.value_range(range).0
+    }
+
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn dedup(&mut self) {
+        let mut removed = vec![];
+        self.data.dedup_by(|a, b| {
+            if a == b {
+                removed.push(*a);
+                true
+            } else {
+                false
+            }
+        });
+        self.remove_cache(&removed);
+    }
+
+    pub fn distinct_count(&self) -> Result<usize, Error> {
+        self.frequency_table().distinct()
     }
 
     pub fn drain<R>(&mut self, range: R) -> Vec<T>
@@ -143,11 +424,62 @@ where usize: AsPrimitive<T>
         del
     }
 
+    pub fn expanding(&mut self, v: &[T]) -> Expanding<T> {
+        let mut expanding = Expanding::with_capacity(v.len());
+        for x in v.iter() {
+            self.push(*x);
+            expanding.mean.push(self.mean().unwrap());
+            expanding.stddev.push(self.stddev().unwrap());
+            expanding.min.push(self.min().unwrap());
+            expanding.max.push(self.max().unwrap());
+        }
+        expanding
+    }
+
+    pub fn extend_from_slice(&mut self, other: &[T]) {
+        other.iter().for_each(|x| self.push(*x));
+    }
+
+    pub fn fraction_above(&self, threshold: T) -> Option<T> {
+        if self.length > 0 {
+            let below = self.order().rank(threshold, true).0;
+            Some((self.length - below).as_() / self.length.as_())
+        } else {
+            None
+        }
+    }
+
+    pub fn frequencies(&self) -> Result<Vec<(T, usize)>, Error> {
+        self.frequency_table().frequencies()
+    }
+
+    pub fn frequency(&self, x: T) -> Result<usize, Error> {
+        self.frequency_table().frequency(x)
+    }
+
+    /// Geometric mean from the running sum of logarithms.
+    pub fn geometric_mean(&self) -> Result<T, Error> {
+        self.check_positive()?;
+        Ok((self.log_reciprocal_sums().0 / self.length.as_()).exp())
+    }
+
+    /// Harmonic mean from the running sum of reciprocals.
+    pub fn harmonic_mean(&self) -> Result<T, Error> {
+        self.check_positive()?;
+        Ok(self.length.as_() / self.log_reciprocal_sums().1)
+    }
+
     pub fn insert(&mut self, index: usize, element: T) {
         self.data.insert(index, element);
         self.add_cache(element);
     }
 
+    /// Interquartile range, the distance between the first and third quartiles.
+    pub fn iqr(&self) -> Option<T> {
+        let order = self.order();
+        Some(self.quantile_of(&order, T::from(0.75).unwrap())? - self.quantile_of(&order, T::from(0.25).unwrap())?)
+    }
+
     pub fn is_empty(&self) -> bool {
         self.data.is_empty()
     }
@@ -156,6 +488,52 @@ where usize: AsPrimitive<T>
         self.data.len()
     }
 
+    /// Median absolute deviation from the median.
+    pub fn mad(&self) -> Option<T> {
+        let order = self.order();
+        let median = self.quantile_of(&order, T::from(0.5).unwrap())?;
+        let k = self.length / 2;
+        if self.length % 2 == 1 {
+            Some(self.kth_deviation(&order, median, k))
+        } else {
+            Some((self.kth_deviation(&order, median, k - 1) + self.kth_deviation(&order, median, k)) / T::from(2.0).unwrap())
+        }
+    }
+
+    /// Median absolute deviation scaled to estimate the standard deviation of normal data.
+    pub fn mad_normal(&self) -> Option<T> {
+        Some(self.mad()? * T::from(MAD_NORMAL).unwrap())
+    }
+
+    pub fn map_in_place<F>(&mut self, mut f: F)
+    where
+        F: FnMut(T) -> T,
+    {
+        let data = std::mem::take(&mut self.data);
+        self.reset();
+        self.data = data;
+        for i in 0..self.data.len() {
+            let x = f(self.data[i]);
+            self.data[i] = x;
+            self.add_cache(x);
+        }
+    }
+
+    pub fn median(&self) -> Option<T> {
+        self.quantile(T::from(0.5).unwrap())
+    }
+
+    /// One of the most frequent values, found in O(1) with `with_frequency_table`. `modes`
+    /// lists all of them in order. The frequency queries return `Err(Error::OutOfRange)` while
+    /// a value falls in a bin whose index does not fit in an i64.
+    pub fn mode(&self) -> Result<T, Error> {
+        self.frequency_table().mode()
+    }
+
+    pub fn modes(&self) -> Result<Vec<T>, Error> {
+        self.frequency_table().modes()
+    }
+
     pub fn pop(&mut self) -> Option<T> {
         let option = self.data.pop();
         if let Some(x) = option {
@@ -164,6 +542,26 @@ where usize: AsPrimitive<T>
         option
     }
 
+    /// Generalized mean `(sum(x^p) / n)^(1/p)` of positive values, which is the geometric mean
+    /// for `p = 0`. The exponents -1, 0, 1 and 2 use running sums; other exponents take a pass
+    /// over the data.
+    pub fn power_mean(&self, p: T) -> Result<T, Error> {
+        self.check_positive()?;
+        let two = T::from(2.0).unwrap();
+        if p == T::zero() {
+            self.geometric_mean()
+        } else if p == -T::one() {
+            self.harmonic_mean()
+        } else if p == T::one() {
+            Ok(self.sum / self.length.as_())
+        } else if p == two {
+            self.quadratic_mean()
+        } else {
+            let total = self.data.iter().fold(T::zero(), |total, x| total + x.powf(p));
+            Ok((total / self.length.as_()).powf(T::one() / p))
+        }
+    }
+
     pub fn push(&mut self, x: T) {
         self.data.push(x);
         self.add_cache(x);
@@ -173,6 +571,19 @@ where usize: AsPrimitive<T>
         v.iter().for_each(|x| self.push(*x));
     }
 
+    /// Root mean square, which unlike the other power means accepts any value.
+    pub fn quadratic_mean(&self) -> Result<T, Error> {
+        if self.length == 0 {
+            return Err(Error::Empty);
+        }
+        Ok((T::max(T::zero(), self.sum_of_squares) / self.length.as_()).sqrt())
+    }
+
+    /// Quantile by linear interpolation between order statistics, matching `stats::quantile`.
+    pub fn quantile(&self, q: T) -> Option<T> {
+        self.quantile_of(&self.order(), q)
+    }
+
     pub fn remove(&mut self, index: usize) -> T {
         let x = self.data.remove(index);
         self.del_cache(x);
@@ -192,6 +603,49 @@ where usize: AsPrimitive<T>
         }
     }
 
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let mut removed = vec![];
+        self.data.retain(|x| {
+            if f(x) {
+                true
+            } else {
+                removed.push(*x);
+                false
+            }
+        });
+        self.remove_cache(&removed);
+    }
+
+    pub fn reverse(&mut self) {
+        self.data.reverse();
+    }
+
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        let x = std::mem::replace(&mut self.data[index], value);
+        self.replace_cache(x, value);
+        x
+    }
+
+    /// Groups values into bins of the given width for the mode and frequency queries, or
+    /// compares them exactly with `None`. The width must be finite and positive.
+    pub fn set_bin_width(&mut self, bin_width: Option<T>) -> Result<(), Error> {
+        if bin_width.is_some_and(|width| !width.is_finite() || width <= T::zero()) {
+            return Err(Error::InvalidParameter("bin width must be finite and positive"));
+        }
+        self.bin_width = bin_width;
+        if self.frequency.is_some() {
+            self.frequency = Some(FrequencyTable::from_values(bin_width, &self.data));
+        }
+        Ok(())
+    }
+
+    pub fn sort(&mut self) {
+        self.data.sort_by(total_cmp);
+    }
+
     pub fn splice<R>(&mut self, range: R, replace_with: Vec<T>) -> Vec<T> 
     where
         R: RangeBounds<usize>,
@@ -220,6 +674,13 @@ where usize: AsPrimitive<T>
         del
     }
 
+    pub fn sum_in_value_range<R>(&self, range: &R) -> T
+    where
+        R: RangeBounds<T>,
+    {
+        self.value_range(range).1
+    }
+
     pub fn swap_remove(&mut self, index: usize) -> T {
         let x = self.remove(index);
         let last_val = self.data[self.length-1];
@@ -237,6 +698,16 @@ where usize: AsPrimitive<T>
         }
     }
 
+    /// Mean after dropping `floor(proportion * len)` values from each end, for a proportion
+    /// in [0, 0.5).
+    pub fn trimmed_mean(&self, proportion: T) -> Option<T> {
+        let g = self.trimmed_count(proportion)?;
+        let order = self.order();
+        let (low, _) = order.prefix(g);
+        let (high, _) = order.prefix(self.length - g);
+        Some((high - low) / (self.length - 2 * g).as_())
+    }
+
     pub fn truncate(&mut self, len: usize) {
         let length = self.length;
         if len < length {
@@ -246,13 +717,118 @@ where usize: AsPrimitive<T>
         }
     }
 
+    pub fn update<F>(&mut self, index: usize, f: F)
+    where
+        F: FnOnce(T) -> T,
+    {
+        let x = f(self.data[index]);
+        self.set(index, x);
+    }
+
+    /// Mean after clamping `floor(proportion * len)` values at each end to the nearest value
+    /// that is kept, for a proportion in [0, 0.5).
+    pub fn winsorized_mean(&self, proportion: T) -> Option<T> {
+        let g = self.trimmed_count(proportion)?;
+        Some(self.winsorized_sums(g).0 / self.length.as_())
+    }
+
+    /// Population variance of the winsorized values.
+    pub fn winsorized_variance(&self, proportion: T) -> Option<T> {
+        let g = self.trimmed_count(proportion)?;
+        let (sum, sum_of_squares) = self.winsorized_sums(g);
+        moments::variance(self.length, sum, sum_of_squares)
+    }
+
+}
+
+impl<T: Float + Default + AddAssign + SubAssign + AsPrimitive<T>> FromIterator<T> for Stats<T> 
+where usize: AsPrimitive<T>
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut stats = Stats::new();
+        stats.extend(iter);
+        stats
+    }
+}
+
+impl<T: Float + Default + AddAssign + SubAssign + AsPrimitive<T>> Extend<T> for Stats<T> 
+where usize: AsPrimitive<T>
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        iter.into_iter().for_each(|x| self.push(x));
+    }
+}
+
+impl<'a, T: Float + Default + AddAssign + SubAssign + AsPrimitive<T>> Extend<&'a T> for Stats<T> 
+where usize: AsPrimitive<T>
+{
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        iter.into_iter().for_each(|x| self.push(*x));
+    }
+}
+
+impl<T: Float + Default + AddAssign + SubAssign + AsPrimitive<T>> IntoIterator for Stats<T> {
+    type Item = T;
+    type IntoIter = vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+impl<'a, T: Float + Default + AddAssign + SubAssign + AsPrimitive<T>> IntoIterator for &'a Stats<T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.iter()
+    }
+}
+
+impl<I: SliceIndex<[T]>, T: Float + Default + AddAssign + SubAssign + AsPrimitive<T>> Index<I> for Stats<T> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.data[index]
+    }
+}
+
+impl<T: Float + Default + AddAssign + SubAssign + AsPrimitive<T>> From<Vec<T>> for Stats<T> 
+where usize: AsPrimitive<T>
+{
+    fn from(v: Vec<T>) -> Self {
+        let mut stats = Stats::new();
+        v.iter().for_each(|x| stats.add_cache(*x));
+        stats.data = v;
+        stats
+    }
+}
+
+impl<T: Float + Default + AddAssign + SubAssign + AsPrimitive<T>> From<&[T]> for Stats<T> 
+where usize: AsPrimitive<T>
+{
+    fn from(v: &[T]) -> Self {
+        v.iter().copied().collect()
+    }
+}
+
+impl<T: Float + Default + AddAssign + SubAssign + AsPrimitive<T>> AsRef<[T]> for Stats<T> {
+    fn as_ref(&self) -> &[T] {
+        &self.data
+    }
 }
 
 #[cfg(test)]
+// The original tests build their inputs with vec!
+#[allow(clippy::useless_vec)]
 mod tests {
     use float_cmp::approx_eq;
     use super::Stats;
+    use crate::test_util::values;
     use crate::stats::{mean, stddev, min, max};
+    use crate::stats::{iqr, mad, median, trimmed_mean, winsorized_mean, winsorized_variance};
+    use crate::stats::{harmonic_mean, power_mean, quadratic_mean};
+    use crate::error::Error;
 
     #[test]
     fn reset_test() {
@@ -263,6 +839,46 @@ mod tests {
         assert_eq!(vfs.data(), vec![])
     }
 
+    #[test]
+    fn collect_extend_test() {
+        let vs = [1.0, -2.0, 3.0];
+        let mut vfs: Stats<f64> = vs.iter().copied().collect();
+        vfs.extend(vec![4.0, 5.0]);
+        vfs.extend(&[-6.0]);
+
+        let vs = vec![1.0, -2.0, 3.0, 4.0, 5.0, -6.0];
+        assert_eq!(vs, vfs.data());
+        assert_eq!(mean(&vs), vfs.mean());
+        assert_eq!(stddev(&vs), vfs.stddev());
+        assert_eq!(min(&vs), vfs.min());
+        assert_eq!(max(&vs), vfs.max());
+    }
+
+    #[test]
+    fn iterate_index_test() {
+        let vs = vec![1.0, 2.0, 3.0];
+        let vfs = Stats::from(vs.clone());
+
+        assert_eq!(vfs[1], 2.0);
+        assert_eq!(&vfs[1..], &vs[1..]);
+        assert_eq!(vfs.as_ref(), &vs[..]);
+        assert_eq!((&vfs).into_iter().sum::<f64>(), 6.0);
+        assert_eq!(vs, vfs.into_iter().collect::<Vec<f64>>());
+    }
+
+    #[test]
+    fn from_test() {
+        let vs = vec![4.0, -1.0, 3.0];
+        let mut vfs = Stats::from(vs.clone());
+        let mut sfs = Stats::from(&vs[..]);
+
+        assert_eq!(vs, vfs.data());
+        assert_eq!(mean(&vs), vfs.mean());
+        assert_eq!(stddev(&vs), sfs.stddev());
+        assert_eq!(min(&vs), sfs.min());
+        assert_eq!(max(&vs), vfs.max());
+    }
+
     #[test]
     fn mean_test() {
         let vs = vec![1.0, 2.0];
@@ -322,6 +938,22 @@ mod tests {
         assert_eq!(max(&vs), vfs.max());
     }
 
+    #[test]
+    fn dedup_test() {
+        let mut vs = vec![3.0, -1.0, 4.0, 4.0, -5.0, 9.0];
+        vs.dedup();
+
+        let mut vfs = Stats::new();
+        vfs.push_vec(vec![3.0, -1.0, 4.0, 4.0, -5.0, 9.0]);
+        vfs.dedup();
+
+        assert_eq!(vs, vfs.data());
+        assert_eq!(mean(&vs), vfs.mean());
+        assert!( approx_eq!(f64, stddev(&vs).unwrap(), vfs.stddev().unwrap(), epsilon = 1e-15) );
+        assert_eq!(min(&vs), vfs.min());
+        assert_eq!(max(&vs), vfs.max());
+    }
+
     #[test]
     fn drain_test() {
         let mut vs = vec![1.0, 2.0, 3.0, 4.0, 1.0, 4.0];
@@ -339,6 +971,40 @@ mod tests {
         assert_eq!(max(&vs), vfs.max());
     }
 
+    #[test]
+    fn expanding_test() {
+        let vs = vec![4.0, -1.0, 3.0, 7.0, -2.0];
+
+        let mut vfs = Stats::new();
+        let efs = vfs.expanding(&vs);
+
+        assert_eq!(efs.len(), vs.len());
+        for i in 0..vs.len() {
+            let head = &vs[..i + 1];
+            assert_eq!(mean(head).unwrap(), efs.mean[i]);
+            assert!( approx_eq!(f64, stddev(head).unwrap(), efs.stddev[i], epsilon = 1e-15) );
+            assert_eq!(min(head).unwrap(), efs.min[i]);
+            assert_eq!(max(head).unwrap(), efs.max[i]);
+        }
+        assert_eq!(vs, vfs.data());
+    }
+
+    #[test]
+    fn extend_from_slice_test() {
+        let mut vs = vec![3.0, -1.0, 4.0, 4.0, -5.0, 9.0];
+        vs.extend_from_slice(&[10.0, -7.0]);
+
+        let mut vfs = Stats::new();
+        vfs.push_vec(vec![3.0, -1.0, 4.0, 4.0, -5.0, 9.0]);
+        vfs.extend_from_slice(&[10.0, -7.0]);
+
+        assert_eq!(vs, vfs.data());
+        assert_eq!(mean(&vs), vfs.mean());
+        assert!( approx_eq!(f64, stddev(&vs).unwrap(), vfs.stddev().unwrap(), epsilon = 1e-15) );
+        assert_eq!(min(&vs), vfs.min());
+        assert_eq!(max(&vs), vfs.max());
+    }
+
     #[test]
     fn insert_test() {
         let mut vs = vec![1.0, 2.0, 3.0];
@@ -377,6 +1043,22 @@ mod tests {
         assert_eq!(len_s, len_fs)
     }
 
+    #[test]
+    fn map_in_place_test() {
+        let mut vs = vec![3.0, -1.0, 4.0, 4.0, -5.0, 9.0];
+        vs.iter_mut().for_each(|x| *x = *x * 2.0 - 1.0);
+
+        let mut vfs = Stats::new();
+        vfs.push_vec(vec![3.0, -1.0, 4.0, 4.0, -5.0, 9.0]);
+        vfs.map_in_place(|x| x * 2.0 - 1.0);
+
+        assert_eq!(vs, vfs.data());
+        assert_eq!(mean(&vs), vfs.mean());
+        assert!( approx_eq!(f64, stddev(&vs).unwrap(), vfs.stddev().unwrap(), epsilon = 1e-15) );
+        assert_eq!(min(&vs), vfs.min());
+        assert_eq!(max(&vs), vfs.max());
+    }
+
     #[test]
     fn remove_test() {
         let mut vs = vec![1.0, 2.0, 3.0];
@@ -414,6 +1096,86 @@ mod tests {
         assert_eq!(max(&vs), vfs.max());       
     }
 
+    #[test]
+    fn retain_test() {
+        let mut vs = vec![3.0, -1.0, 4.0, 4.0, -5.0, 9.0];
+        vs.retain(|x| *x > -5.0 && *x < 9.0);
+
+        let mut vfs = Stats::new();
+        vfs.push_vec(vec![3.0, -1.0, 4.0, 4.0, -5.0, 9.0]);
+        vfs.retain(|x| *x > -5.0 && *x < 9.0);
+
+        assert_eq!(vs, vfs.data());
+        assert_eq!(mean(&vs), vfs.mean());
+        assert!( approx_eq!(f64, stddev(&vs).unwrap(), vfs.stddev().unwrap(), epsilon = 1e-15) );
+        assert_eq!(min(&vs), vfs.min());
+        assert_eq!(max(&vs), vfs.max());
+    }
+
+    #[test]
+    fn reverse_test() {
+        let mut vs = vec![3.0, -1.0, 4.0, 4.0, -5.0, 9.0];
+        vs.reverse();
+
+        let mut vfs = Stats::new();
+        vfs.push_vec(vec![3.0, -1.0, 4.0, 4.0, -5.0, 9.0]);
+        vfs.reverse();
+
+        assert_eq!(vs, vfs.data());
+        assert_eq!(mean(&vs), vfs.mean());
+        assert!( approx_eq!(f64, stddev(&vs).unwrap(), vfs.stddev().unwrap(), epsilon = 1e-15) );
+        assert_eq!(min(&vs), vfs.min());
+        assert_eq!(max(&vs), vfs.max());
+    }
+
+    #[test]
+    fn set_test() {
+        let mut vs = vec![3.0, -1.0, 4.0, 4.0, -5.0, 9.0];
+        vs[5] = 2.0;
+        vs[4] = 0.0;
+        vs[0] = 12.0;
+
+        let mut vfs = Stats::new();
+        vfs.push_vec(vec![3.0, -1.0, 4.0, 4.0, -5.0, 9.0]);
+        assert_eq!(vfs.set(5, 2.0), 9.0);
+        vfs.set(4, 0.0);
+        vfs.set(0, 12.0);
+
+        assert_eq!(vs, vfs.data());
+        assert_eq!(mean(&vs), vfs.mean());
+        assert!( approx_eq!(f64, stddev(&vs).unwrap(), vfs.stddev().unwrap(), epsilon = 1e-15) );
+        assert_eq!(min(&vs), vfs.min());
+        assert_eq!(max(&vs), vfs.max());
+    }
+
+    #[test]
+    fn sort_test() {
+        let mut vs = vec![3.0, -1.0, 4.0, 4.0, -5.0, 9.0];
+        vs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let mut vfs = Stats::new();
+        vfs.push_vec(vec![3.0, -1.0, 4.0, 4.0, -5.0, 9.0]);
+        vfs.sort();
+
+        assert_eq!(vs, vfs.data());
+        assert_eq!(mean(&vs), vfs.mean());
+        assert!( approx_eq!(f64, stddev(&vs).unwrap(), vfs.stddev().unwrap(), epsilon = 1e-15) );
+        assert_eq!(min(&vs), vfs.min());
+        assert_eq!(max(&vs), vfs.max());
+    }
+
+    #[test]
+    fn sort_nan_test() {
+        let mut vfs = Stats::new();
+        vfs.push_vec(vec![2.0, f64::NAN, 0.0, -1.0, -0.0, f64::NEG_INFINITY]);
+        vfs.sort();
+
+        let sorted = vfs.data();
+        assert_eq!(&sorted[..5], &[f64::NEG_INFINITY, -1.0, -0.0, 0.0, 2.0]);
+        assert!(sorted[2].is_sign_negative());
+        assert!(sorted[5].is_nan());
+    }
+
     #[test]
     fn splice_test() {
         let mut vs = vec![1.0, 2.0, 3.0];
@@ -481,4 +1243,215 @@ mod tests {
         assert_eq!(max(&vs), vfs.max());  
     }
 
+    #[test]
+    fn update_test() {
+        let mut vs = vec![3.0, -1.0, 4.0, 4.0, -5.0, 9.0];
+        vs[5] -= 20.0;
+
+        let mut vfs = Stats::new();
+        vfs.push_vec(vec![3.0, -1.0, 4.0, 4.0, -5.0, 9.0]);
+        vfs.update(5, |x| x - 20.0);
+
+        assert_eq!(vs, vfs.data());
+        assert_eq!(mean(&vs), vfs.mean());
+        assert!( approx_eq!(f64, stddev(&vs).unwrap(), vfs.stddev().unwrap(), epsilon = 1e-15) );
+        assert_eq!(min(&vs), vfs.min());
+        assert_eq!(max(&vs), vfs.max());
+    }
+
+    #[test]
+    fn value_range_test() {
+        let mut vfs = Stats::new().with_order_index();
+        vfs.push_vec(vec![3.0, -1.0, 4.0, 1.0, 5.0, 9.0, 2.0, 6.0, 5.0]);
+        vfs.remove(2);
+        vfs.set(0, 7.0);
+        vfs.insert(3, 1.0);
+        vfs.retain(|x| *x != 9.0);
+        vfs.splice(1..3, vec![8.0, 5.0]);
+        vfs.map_in_place(|x| x - 1.0);
+        vfs.pop();
+
+        let vs = vfs.data().to_vec();
+        let count = |f: &dyn Fn(f64) -> bool| vs.iter().filter(|x| f(**x)).count();
+        let sum = |f: &dyn Fn(f64) -> bool| vs.iter().filter(|x| f(**x)).sum::<f64>();
+
+        assert_eq!(vfs.count_in_value_range(&(1.0..4.0)), count(&|x| (1.0..4.0).contains(&x)));
+        assert_eq!(vfs.count_in_value_range(&(..=4.0)), count(&|x| x <= 4.0));
+        assert_eq!(vfs.count_in_value_range(&(4.0..)), count(&|x| x >= 4.0));
+        assert_eq!(vfs.count_in_value_range(&(5.0..1.0)), 0);
+        assert_eq!(vfs.sum_in_value_range(&(0.0..=4.0)), sum(&|x| (0.0..=4.0).contains(&x)));
+        assert_eq!(vfs.sum_in_value_range(&(..)), vs.iter().sum::<f64>());
+        assert_eq!(vfs.fraction_above(4.0), Some(count(&|x| x > 4.0) as f64 / vs.len() as f64));
+
+        let plain = Stats::from(vs.clone());
+        assert_eq!(plain.count_in_value_range(&(1.0..4.0)), vfs.count_in_value_range(&(1.0..4.0)));
+        assert_eq!(plain.sum_in_value_range(&(..=4.0)), vfs.sum_in_value_range(&(..=4.0)));
+
+        vfs.reset();
+        assert_eq!(vfs.count_in_value_range(&(..)), 0);
+        assert_eq!(vfs.fraction_above(4.0), None);
+    }
+
+    #[test]
+    fn mode_test() {
+        let mut vfs = Stats::new().with_frequency_table();
+        vfs.push_vec(vec![2.0, 3.0, 2.0, 5.0, 3.0, 3.0]);
+
+        assert_eq!(vfs.mode(), Ok(3.0));
+        assert_eq!(vfs.distinct_count(), Ok(3));
+        assert_eq!(vfs.frequencies(), Ok(vec![(2.0, 2), (3.0, 3), (5.0, 1)]));
+
+        vfs.remove(1);
+        vfs.set(3, 2.0);
+        assert_eq!(vfs.modes(), Ok(vec![2.0]));
+        vfs.truncate(3);
+        assert_eq!(vfs.modes(), Ok(vec![2.0]));
+        assert_eq!(vfs.frequency(5.0), Ok(1));
+        assert_eq!(vfs.frequency(3.0), Ok(0));
+
+        vfs.push_vec(vec![5.2, 4.9]);
+        assert!(vfs.set_bin_width(Some(0.0)).is_err());
+        assert!(vfs.set_bin_width(Some(f64::NAN)).is_err());
+        vfs.set_bin_width(Some(1.0)).unwrap();
+        assert_eq!(vfs.modes(), Ok(vec![2.0, 5.0]));
+        assert_eq!(vfs.distinct_count(), Ok(3));
+        let mut plain = Stats::from(vfs.data().to_vec());
+        plain.set_bin_width(Some(1.0)).unwrap();
+        assert_eq!(plain.frequencies(), vfs.frequencies());
+
+        vfs.push(1e300);
+        assert_eq!(vfs.mode(), Err(Error::OutOfRange));
+        assert_eq!(plain.frequency(1e300), Err(Error::OutOfRange));
+        vfs.pop();
+        assert_eq!(vfs.modes(), Ok(vec![2.0, 5.0]));
+
+        vfs.reset();
+        assert_eq!(vfs.mode(), Err(Error::Empty));
+        assert_eq!(vfs.distinct_count(), Ok(0));
+    }
+
+    #[test]
+    fn robust_test() {
+        let mut vfs = Stats::new();
+        assert_eq!(vfs.median(), None);
+        assert_eq!(vfs.mad(), None);
+        vfs.push_vec(vec![1.0, 1.0, 2.0, 2.0, 4.0, 6.0, 9.0]);
+
+        assert_eq!(vfs.median(), Some(2.0));
+        assert_eq!(vfs.mad(), Some(1.0));
+        assert!( approx_eq!(f64, vfs.mad_normal().unwrap(), 1.482602218505602, epsilon = 1e-12) );
+        assert_eq!(vfs.iqr(), Some(5.0 - 1.5));
+        assert_eq!(vfs.quantile(1.0), Some(9.0));
+        assert_eq!(vfs.trimmed_mean(0.2), Some(3.0));
+        assert_eq!(vfs.winsorized_mean(0.2), Some(22.0 / 7.0));
+        assert_eq!(vfs.trimmed_mean(0.5), None);
+
+        vfs.push(1000.0);
+        assert_eq!(vfs.median(), Some(3.0));
+        assert!(vfs.mad().unwrap() < 3.0);
+    }
+
+    #[test]
+    fn rolling_robust_test() {
+        let window = 25;
+        let vs: Vec<f64> = values(150, 11).iter().map(|x| (x * 20.0).floor()).collect();
+        let mut vfs = Stats::new().with_order_index();
+        for x in vs.iter() {
+            vfs.push(*x);
+            if vfs.len() > window {
+                vfs.remove(0);
+            }
+            let data = vfs.data().to_vec();
+            assert_eq!(vfs.median(), median(&data));
+            assert_eq!(vfs.mad(), mad(&data));
+            assert_eq!(vfs.iqr(), iqr(&data));
+            for p in [0.0, 0.1, 0.25].iter() {
+                assert!( approx_eq!(f64, vfs.trimmed_mean(*p).unwrap(), trimmed_mean(&data, *p).unwrap(), epsilon = 1e-9) );
+                assert!( approx_eq!(f64, vfs.winsorized_mean(*p).unwrap(), winsorized_mean(&data, *p).unwrap(), epsilon = 1e-9) );
+                assert!( approx_eq!(f64, vfs.winsorized_variance(*p).unwrap(), winsorized_variance(&data, *p).unwrap(), epsilon = 1e-9) );
+            }
+        }
+    }
+
+    #[test]
+    fn rolling_nan_test() {
+        // A NaN that has left the window no longer affects the robust statistics.
+        for mut vfs in [Stats::new(), Stats::new().with_order_index()] {
+            vfs.push_vec(vec![1.0, 2.0, 3.0]);
+            vfs.push(f64::NAN);
+            vfs.pop();
+            assert_eq!(vfs.count_in_value_range(&(..)), vfs.len());
+            assert_eq!(vfs.trimmed_mean(0.0), Some(2.0));
+
+            for x in [f64::NAN, 0.5, 4.0, 5.0].iter() {
+                vfs.push(*x);
+                vfs.remove(0);
+            }
+            assert_eq!(vfs.data(), &[0.5, 4.0, 5.0]);
+            assert_eq!(vfs.count_in_value_range(&(..)), 3);
+            assert_eq!(vfs.median(), Some(4.0));
+            assert_eq!(vfs.mad(), Some(1.0));
+            assert_eq!(vfs.quantile(0.0), Some(0.5));
+            assert_eq!(vfs.trimmed_mean(0.0), Some(9.5 / 3.0));
+            assert_eq!(vfs.winsorized_mean(0.0), Some(9.5 / 3.0));
+        }
+    }
+
+    #[test]
+    fn power_means_test() {
+        let mut vfs = Stats::new();
+        assert_eq!(vfs.geometric_mean(), Err(Error::Empty));
+        assert_eq!(vfs.quadratic_mean(), Err(Error::Empty));
+        vfs.push_vec(vec![1.0, 2.0, 4.0, 8.0, 0.5]);
+
+        let data = vfs.data().to_vec();
+        assert!( approx_eq!(f64, vfs.geometric_mean().unwrap(), 2.0, epsilon = 1e-12) );
+        assert!( approx_eq!(f64, vfs.harmonic_mean().unwrap(), harmonic_mean(&data).unwrap(), epsilon = 1e-12) );
+        assert!( approx_eq!(f64, vfs.quadratic_mean().unwrap(), quadratic_mean(&data).unwrap(), epsilon = 1e-12) );
+        assert!( approx_eq!(f64, vfs.power_mean(1.0).unwrap(), mean(&data).unwrap(), epsilon = 1e-12) );
+        for p in [-2.0, -1.0, 0.0, 0.5, 2.0, 3.0].iter() {
+            assert!( approx_eq!(f64, vfs.power_mean(*p).unwrap(), power_mean(&data, *p).unwrap(), epsilon = 1e-12) );
+        }
+        assert!(vfs.harmonic_mean().unwrap() < vfs.geometric_mean().unwrap());
+        assert!(vfs.geometric_mean().unwrap() < vfs.quadratic_mean().unwrap());
+    }
+
+    #[test]
+    fn non_finite_mean_test() {
+        let mut vfs = Stats::new();
+        vfs.push_vec(vec![2.0, 8.0]);
+        vfs.push(f64::INFINITY);
+        assert_eq!(vfs.geometric_mean(), Ok(f64::INFINITY));
+        assert!( approx_eq!(f64, vfs.harmonic_mean().unwrap(), 4.8, epsilon = 1e-12) );
+        vfs.pop();
+        assert!( approx_eq!(f64, vfs.geometric_mean().unwrap(), 4.0, epsilon = 1e-12) );
+
+        // The reciprocal of a subnormal value overflows to inf
+        vfs.push(1e-310);
+        assert_eq!(vfs.harmonic_mean(), Ok(0.0));
+        vfs.remove(2);
+        assert!( approx_eq!(f64, vfs.harmonic_mean().unwrap(), 3.2, epsilon = 1e-12) );
+        assert!( approx_eq!(f64, vfs.geometric_mean().unwrap(), 4.0, epsilon = 1e-12) );
+    }
+
+    #[test]
+    fn non_positive_test() {
+        let mut vfs = Stats::new();
+        vfs.push_vec(vec![3.0, 0.0, 12.0, -1.0]);
+        assert_eq!(vfs.geometric_mean(), Err(Error::NonPositive));
+        assert_eq!(vfs.harmonic_mean(), Err(Error::NonPositive));
+        assert_eq!(vfs.power_mean(2.0), Err(Error::NonPositive));
+        assert!( approx_eq!(f64, vfs.quadratic_mean().unwrap(), f64::sqrt(154.0 / 4.0), epsilon = 1e-12) );
+
+        vfs.remove(1);
+        vfs.set(2, 1.0);
+        assert!( approx_eq!(f64, vfs.geometric_mean().unwrap(), f64::cbrt(36.0), epsilon = 1e-12) );
+        vfs.set(0, -3.0);
+        assert_eq!(vfs.geometric_mean(), Err(Error::NonPositive));
+        vfs.retain(|x| *x > 0.0);
+        assert!( approx_eq!(f64, vfs.harmonic_mean().unwrap(), 2.0 / (1.0 / 12.0 + 1.0), epsilon = 1e-12) );
+        vfs.reset();
+        assert_eq!(vfs.harmonic_mean(), Err(Error::Empty));
+    }
+
 }
\ No newline at end of file
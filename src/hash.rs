@@ -0,0 +1,87 @@
+use std::hash::{Hash, Hasher};
+
+const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const PRIME: u64 = 0x0100_0000_01b3;
+
+// FNV-1a followed by the murmur3 finalizer. Unlike the standard library hasher its output is
+// fixed across releases, and integers are hashed as little-endian bytes with `usize` widened
+// to 64 bits, so sketches built from it can be serialized on one target and merged on another.
+#[derive(Clone, Debug)]
+pub(crate) struct StableHasher {
+    state: u64,
+}
+
+impl StableHasher {
+    pub(crate) fn with_seed(seed: u64) -> Self {
+        StableHasher { state: OFFSET ^ seed }
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.state ^= *byte as u64;
+            self.state = self.state.wrapping_mul(PRIME);
+        }
+    }
+
+    // The default integer methods write native-endian bytes. The signed ones forward to these.
+    fn write_u16(&mut self, x: u16) {
+        self.write(&x.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, x: u32) {
+        self.write(&x.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, x: u64) {
+        self.write(&x.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, x: u128) {
+        self.write(&x.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, x: usize) {
+        self.write_u64(x as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        let mut z = self.state;
+        z = (z ^ (z >> 33)).wrapping_mul(0xff51_afd7_ed55_8ccd);
+        z = (z ^ (z >> 33)).wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        z ^ (z >> 33)
+    }
+}
+
+pub(crate) fn hash<H: Hash + ?Sized>(item: &H, seed: u64) -> u64 {
+    let mut hasher = StableHasher::with_seed(seed);
+    item.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Hash of a float by its bits, with -0.0 folded into 0.0.
+pub(crate) fn hash_f64(x: f64, seed: u64) -> u64 {
+    hash(&(x + 0.0).to_bits(), seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::Hasher;
+    use super::{hash, StableHasher};
+
+    #[test]
+    fn portable_test() {
+        // Integers hash like their little-endian bytes, and usize like u64, on every target.
+        let mut bytes = StableHasher::with_seed(7);
+        bytes.write(&[4, 3, 2, 1]);
+        let mut int = StableHasher::with_seed(7);
+        int.write_u32(0x0102_0304);
+        assert_eq!(int.finish(), bytes.finish());
+        assert_eq!(hash(&42usize, 0), hash(&42u64, 0));
+        assert_eq!(hash(&-1i32, 0), hash(&u32::MAX, 0));
+        // Pinned values that every target must reproduce
+        assert_eq!(hash("abc", 0), 0x3ee0_641e_1a67_4131);
+        assert_eq!(hash(&123_456_789u64, 0), 0x4bac_9369_e0de_6811);
+    }
+}
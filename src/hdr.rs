@@ -0,0 +1,482 @@
+use std::f64;
+
+use crate::error::Error;
+
+/// A run of equivalent values sharing one counter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HdrBucket {
+    pub lowest: u64,
+    pub highest: u64,
+    pub count: u64,
+}
+
+/// High dynamic range histogram of integer values, such as latencies in microseconds.
+///
+/// Values between `lowest` and `highest` are recorded with a relative error of at most
+/// `10^-sigfig`, using buckets of doubling width that are each split into the same number of
+/// linear sub-buckets.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HdrHistogram {
+    lowest: u64,
+    highest: u64,
+    sigfig: u8,
+    unit_magnitude: u32,
+    sub_bucket_half_count_magnitude: u32,
+    sub_bucket_count: u64,
+    sub_bucket_mask: u64,
+    counts: Vec<u64>,
+    length: u64,
+    max: Option<u64>,
+    min: Option<u64>,
+}
+
+impl HdrHistogram {
+    pub fn new(lowest: u64, highest: u64, sigfig: u8) -> Result<Self, Error> {
+        if lowest < 1 {
+            return Err(Error::InvalidParameter("lowest discernible value must be at least 1"));
+        }
+        if highest < 2 * lowest {
+            return Err(Error::InvalidParameter("highest trackable value must be at least twice the lowest"));
+        }
+        if sigfig > 5 {
+            return Err(Error::InvalidParameter("significant digits must be between 0 and 5"));
+        }
+
+        let single_unit_resolution = 2 * 10u64.pow(sigfig as u32);
+        let sub_bucket_count_magnitude = 64 - (single_unit_resolution - 1).leading_zeros();
+        let sub_bucket_half_count_magnitude = u32::max(sub_bucket_count_magnitude, 1) - 1;
+        let unit_magnitude = 63 - lowest.leading_zeros();
+        if unit_magnitude + sub_bucket_half_count_magnitude + 1 > 63 {
+            return Err(Error::InvalidParameter("lowest discernible value is too large for the precision"));
+        }
+        let sub_bucket_count = 1u64 << (sub_bucket_half_count_magnitude + 1);
+        let sub_bucket_mask = (sub_bucket_count - 1) << unit_magnitude;
+
+        let mut smallest_untrackable = sub_bucket_count << unit_magnitude;
+        let mut bucket_count = 1;
+        while smallest_untrackable <= highest {
+            if smallest_untrackable > u64::MAX / 2 {
+                bucket_count += 1;
+                break;
+            }
+            smallest_untrackable <<= 1;
+            bucket_count += 1;
+        }
+        let counts_len = (bucket_count + 1) << sub_bucket_half_count_magnitude;
+
+        Ok(HdrHistogram {
+            lowest,
+            highest,
+            sigfig,
+            unit_magnitude,
+            sub_bucket_half_count_magnitude,
+            sub_bucket_count,
+            sub_bucket_mask,
+            counts: vec![0; counts_len],
+            length: 0,
+            max: None,
+            min: None,
+        })
+    }
+
+    fn bucket_index(&self, x: u64) -> u32 {
+        let leading_zero_count_base = 64 - self.unit_magnitude - self.sub_bucket_half_count_magnitude - 1;
+        leading_zero_count_base - (x | self.sub_bucket_mask).leading_zeros()
+    }
+
+    fn sub_bucket_index(&self, x: u64, bucket: u32) -> u64 {
+        x >> (bucket + self.unit_magnitude)
+    }
+
+    fn index(&self, x: u64) -> usize {
+        let bucket = self.bucket_index(x);
+        let sub_bucket = self.sub_bucket_index(x, bucket);
+        let half_count = self.sub_bucket_count / 2;
+        (((bucket as u64 + 1) << self.sub_bucket_half_count_magnitude) + sub_bucket - half_count) as usize
+    }
+
+    fn value_from_index(&self, index: usize) -> u64 {
+        let half_count = self.sub_bucket_count / 2;
+        let mut bucket = (index as i64 >> self.sub_bucket_half_count_magnitude) - 1;
+        let mut sub_bucket = (index as u64 & (half_count - 1)) + half_count;
+        if bucket < 0 {
+            sub_bucket -= half_count;
+            bucket = 0;
+        }
+        sub_bucket << (bucket as u32 + self.unit_magnitude)
+    }
+
+    fn equivalent_range(&self, x: u64) -> u64 {
+        let bucket = self.bucket_index(x);
+        let sub_bucket = self.sub_bucket_index(x, bucket);
+        let adjusted = if sub_bucket >= self.sub_bucket_count { bucket + 1 } else { bucket };
+        1 << (self.unit_magnitude + adjusted)
+    }
+
+    pub fn lowest_equivalent(&self, x: u64) -> u64 {
+        let bucket = self.bucket_index(x);
+        let sub_bucket = self.sub_bucket_index(x, bucket);
+        sub_bucket << (bucket + self.unit_magnitude)
+    }
+
+    pub fn highest_equivalent(&self, x: u64) -> u64 {
+        self.lowest_equivalent(x).saturating_add(self.equivalent_range(x) - 1)
+    }
+
+    pub fn median_equivalent(&self, x: u64) -> u64 {
+        self.lowest_equivalent(x).saturating_add(self.equivalent_range(x) / 2)
+    }
+
+    pub fn record(&mut self, x: u64) -> Result<(), Error> {
+        self.record_n(x, 1)
+    }
+
+    /// Records `count` occurrences of `x`. Fails with `Error::OutOfRange` if `x` is outside the
+    /// trackable range or the counts would overflow.
+    pub fn record_n(&mut self, x: u64, count: u64) -> Result<(), Error> {
+        let index = self.index(x);
+        if index >= self.counts.len() {
+            return Err(Error::OutOfRange);
+        }
+        let bucket = self.counts[index].checked_add(count).ok_or(Error::OutOfRange)?;
+        let length = self.length.checked_add(count).ok_or(Error::OutOfRange)?;
+        self.counts[index] = bucket;
+        self.length = length;
+        if self.max.is_none() || self.max < Some(x) {
+            self.max = Some(x)
+        };
+        if self.min.is_none() || self.min > Some(x) {
+            self.min = Some(x)
+        };
+        Ok(())
+    }
+
+    /// Records `x` and back-fills the samples a stalled recorder would have missed, assuming
+    /// one sample was expected every `interval`.
+    pub fn record_correct(&mut self, x: u64, interval: u64) -> Result<(), Error> {
+        self.record(x)?;
+        if interval > 0 && x > interval {
+            let mut missing = x - interval;
+            while missing >= interval {
+                self.record(missing)?;
+                missing -= interval;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn merge(&mut self, other: &HdrHistogram) -> Result<(), Error> {
+        if other.max.is_some_and(|x| self.index(x) >= self.counts.len()) {
+            return Err(Error::OutOfRange);
+        }
+        let (max, min) = (self.max, self.min);
+        for bucket in other.iter_recorded() {
+            self.record_n(bucket.lowest, bucket.count)?;
+        }
+        self.max = Option::max(max, other.max);
+        self.min = match (min, other.min) {
+            (Some(a), Some(b)) => Some(u64::min(a, b)),
+            (a, b) => a.or(b),
+        };
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        self.counts.iter_mut().for_each(|c| *c = 0);
+        self.length = 0;
+        self.max = None;
+        self.min = None;
+    }
+
+    pub fn sigfig(&self) -> u8 {
+        self.sigfig
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn min(&self) -> Option<u64> {
+        self.min
+    }
+
+    pub fn max(&self) -> Option<u64> {
+        self.max
+    }
+
+    pub fn count_at(&self, x: u64) -> u64 {
+        self.counts.get(self.index(x)).copied().unwrap_or(0)
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        if self.length > 0 {
+            let sum: f64 = self.iter_recorded()
+                .map(|b| self.median_equivalent(b.lowest) as f64 * b.count as f64)
+                .sum();
+            Some(sum / self.length as f64)
+        } else {
+            None
+        }
+    }
+
+    pub fn stddev(&self) -> Option<f64> {
+        let mean = self.mean()?;
+        let sum_of_squares: f64 = self.iter_recorded()
+            .map(|b| {
+                let diff = self.median_equivalent(b.lowest) as f64 - mean;
+                diff * diff * b.count as f64
+            })
+            .sum();
+        Some(f64::sqrt(sum_of_squares / self.length as f64))
+    }
+
+    /// Value at or below which `percentile` percent of the recorded values lie, reported as
+    /// the highest value equivalent to the bucket it falls in.
+    pub fn value_at_percentile(&self, percentile: f64) -> Option<u64> {
+        if self.length == 0 || !(0.0..=100.0).contains(&percentile) {
+            return None;
+        }
+        if percentile == 0.0 {
+            return self.min;
+        }
+        let target = u64::max(1, (percentile / 100.0 * self.length as f64).ceil() as u64);
+        let mut count_so_far = 0;
+        for (i, count) in self.counts.iter().enumerate() {
+            count_so_far += count;
+            if count_so_far >= target {
+                let x = self.highest_equivalent(self.value_from_index(i));
+                return Some(u64::min(x, self.max.unwrap()));
+            }
+        }
+        self.max
+    }
+
+    pub fn iter_recorded(&self) -> impl Iterator<Item = HdrBucket> + '_ {
+        self.counts.iter().enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(move |(i, count)| {
+                let x = self.value_from_index(i);
+                HdrBucket { lowest: x, highest: self.highest_equivalent(x), count: *count }
+            })
+    }
+
+    /// Encodes the histogram parameters and extremes followed by the counts as zigzag varints,
+    /// where a negative number stands for a run of empty buckets.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.sigfig];
+        bytes.extend_from_slice(&self.lowest.to_le_bytes());
+        bytes.extend_from_slice(&self.highest.to_le_bytes());
+        bytes.extend_from_slice(&self.min.unwrap_or(0).to_le_bytes());
+        bytes.extend_from_slice(&self.max.unwrap_or(0).to_le_bytes());
+        let end = self.counts.iter().rposition(|c| *c > 0).map_or(0, |i| i + 1);
+        let mut i = 0;
+        while i < end {
+            let count = self.counts[i];
+            if count == 0 {
+                let zeros = self.counts[i..end].iter().take_while(|c| **c == 0).count();
+                write_varint(&mut bytes, zigzag(-(zeros as i64)));
+                i += zeros;
+            } else {
+                write_varint(&mut bytes, zigzag(count as i64));
+                i += 1;
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 33 {
+            return Err(Error::InvalidEncoding);
+        }
+        let word = |i: usize| {
+            let mut word = [0; 8];
+            word.copy_from_slice(&bytes[i..i + 8]);
+            u64::from_le_bytes(word)
+        };
+        let mut histogram = HdrHistogram::new(word(1), word(9), bytes[0])?;
+
+        let mut rest = &bytes[33..];
+        let mut i: usize = 0;
+        while !rest.is_empty() {
+            let value = unzigzag(read_varint(&mut rest)?);
+            if value < 0 {
+                i = usize::try_from(value.unsigned_abs()).ok()
+                    .and_then(|skip| i.checked_add(skip))
+                    .filter(|i| *i <= histogram.counts.len())
+                    .ok_or(Error::InvalidEncoding)?;
+            } else if i < histogram.counts.len() {
+                histogram.record_n(histogram.value_from_index(i), value as u64)
+                    .map_err(|_| Error::InvalidEncoding)?;
+                i += 1;
+            } else {
+                return Err(Error::InvalidEncoding);
+            }
+        }
+        if histogram.length > 0 {
+            histogram.min = Some(word(17));
+            histogram.max = Some(word(25));
+        }
+        Ok(histogram)
+    }
+}
+
+fn zigzag(x: i64) -> u64 {
+    ((x << 1) ^ (x >> 63)) as u64
+}
+
+fn unzigzag(x: u64) -> i64 {
+    (x >> 1) as i64 ^ -((x & 1) as i64)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut x: u64) {
+    while x >= 0x80 {
+        bytes.push((x as u8) | 0x80);
+        x >>= 7;
+    }
+    bytes.push(x as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, Error> {
+    let mut x = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first().ok_or(Error::InvalidEncoding)?;
+        *bytes = rest;
+        x |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(x);
+        }
+    }
+    Err(Error::InvalidEncoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+    use super::{write_varint, zigzag, HdrHistogram};
+    use crate::error::Error;
+    use crate::stats::{mean, quantile};
+
+    #[test]
+    fn new_test() {
+        assert!(HdrHistogram::new(0, 100, 3).is_err());
+        assert!(HdrHistogram::new(10, 15, 3).is_err());
+        assert!(HdrHistogram::new(1, 100, 6).is_err());
+        assert!(HdrHistogram::new(1, 3_600_000_000, 3).is_ok());
+    }
+
+    #[test]
+    fn record_test() {
+        let mut h = HdrHistogram::new(1, 3_600_000_000, 3).unwrap();
+        h.record(1).unwrap();
+        h.record(1000).unwrap();
+        h.record(123_456).unwrap();
+        h.record_n(2047, 3).unwrap();
+
+        assert_eq!(h.len(), 6);
+        assert_eq!(h.min(), Some(1));
+        assert_eq!(h.max(), Some(123_456));
+        assert_eq!(h.count_at(1000), 1);
+        assert_eq!(h.count_at(2047), 3);
+        assert_eq!(h.count_at(123_456), 1);
+        assert_eq!(h.record(u64::MAX), Err(Error::OutOfRange));
+    }
+
+    #[test]
+    fn precision_test() {
+        let h = HdrHistogram::new(1, 3_600_000_000, 3).unwrap();
+        for x in [1, 1000, 2047, 10_000, 123_456, 99_999_999, 3_600_000_000].iter() {
+            let lowest = h.lowest_equivalent(*x);
+            let highest = h.highest_equivalent(*x);
+            assert!(lowest <= *x && *x <= highest);
+            assert!((highest - lowest) as f64 <= *x as f64 / 1000.0 || highest == lowest);
+        }
+        assert_eq!(h.lowest_equivalent(10_007), 10_000);
+        assert_eq!(h.highest_equivalent(10_007), 10_007);
+    }
+
+    #[test]
+    fn percentile_test() {
+        let mut h = HdrHistogram::new(1, 3_600_000_000, 3).unwrap();
+        let vs: Vec<f64> = (1..=10000).map(|x| x as f64).collect();
+        vs.iter().for_each(|x| h.record(*x as u64).unwrap());
+
+        for p in [50.0, 90.0, 99.0, 99.9].iter() {
+            let exact = quantile(&vs, p / 100.0).unwrap();
+            let approx = h.value_at_percentile(*p).unwrap() as f64;
+            assert!((approx - exact).abs() <= exact / 1000.0 + 1.0, "p = {}", p);
+        }
+        assert_eq!(h.value_at_percentile(0.0), Some(1));
+        assert_eq!(h.value_at_percentile(100.0), Some(10000));
+        assert!( approx_eq!(f64, h.mean().unwrap(), mean(&vs).unwrap(), epsilon = 5.0) );
+    }
+
+    #[test]
+    fn record_correct_test() {
+        let mut h = HdrHistogram::new(1, 100_000, 3).unwrap();
+        h.record_correct(1000, 100).unwrap();
+
+        assert_eq!(h.len(), 10);
+        assert_eq!(h.min(), Some(100));
+        assert_eq!(h.count_at(500), 1);
+    }
+
+    #[test]
+    fn merge_iter_test() {
+        let mut a = HdrHistogram::new(1, 1_000_000, 2).unwrap();
+        let mut b = HdrHistogram::new(1, 1_000_000, 2).unwrap();
+        a.record_n(10, 2).unwrap();
+        b.record_n(10, 1).unwrap();
+        b.record(5000).unwrap();
+        a.merge(&b).unwrap();
+
+        let buckets: Vec<_> = a.iter_recorded().collect();
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].count, 3);
+        assert_eq!(buckets[1].count, 1);
+        assert!(buckets[1].lowest <= 5000 && 5000 <= buckets[1].highest);
+        assert_eq!(a.max(), Some(5000));
+
+        let mut small = HdrHistogram::new(1, 100, 2).unwrap();
+        assert_eq!(small.merge(&b), Err(Error::OutOfRange));
+    }
+
+    #[test]
+    fn bytes_test() {
+        let mut h = HdrHistogram::new(1, 3_600_000_000, 3).unwrap();
+        (1..2000).for_each(|x| h.record(x * x).unwrap());
+        let bytes = h.to_bytes();
+        let rt = HdrHistogram::from_bytes(&bytes).unwrap();
+
+        assert!(bytes.len() < 8 * 2000);
+        assert_eq!(rt, h);
+        assert_eq!(rt.value_at_percentile(99.0), h.value_at_percentile(99.0));
+        assert_eq!(rt.iter_recorded().collect::<Vec<_>>(), h.iter_recorded().collect::<Vec<_>>());
+        assert_eq!(HdrHistogram::from_bytes(&bytes[..10]), Err(Error::InvalidEncoding));
+    }
+
+    #[test]
+    fn crafted_bytes_test() {
+        let header = HdrHistogram::new(1, 1000, 2).unwrap().to_bytes();
+        let encode = |values: &[i64]| {
+            let mut bytes = header.clone();
+            values.iter().for_each(|v| write_varint(&mut bytes, zigzag(*v)));
+            bytes
+        };
+
+        // Counts whose total overflows, a skip far past the end and a count after the end
+        assert_eq!(HdrHistogram::from_bytes(&encode(&[i64::MAX, i64::MAX, i64::MAX])), Err(Error::InvalidEncoding));
+        assert_eq!(HdrHistogram::from_bytes(&encode(&[-i64::MAX, 1])), Err(Error::InvalidEncoding));
+        let len = HdrHistogram::new(1, 1000, 2).unwrap().counts.len() as i64;
+        assert_eq!(HdrHistogram::from_bytes(&encode(&[-len, 1])), Err(Error::InvalidEncoding));
+        assert!(HdrHistogram::from_bytes(&encode(&[-(len - 1), 1])).is_ok());
+
+        let mut h = HdrHistogram::new(1, 1000, 2).unwrap();
+        h.record_n(5, u64::MAX).unwrap();
+        assert_eq!(h.record(5), Err(Error::OutOfRange));
+        assert_eq!(h.len(), u64::MAX);
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::f64;
+use std::hash::Hash;
+
+use crate::error::Error;
+use crate::hash::hash;
+
+/// An item tracked by `SpaceSaving` with its estimated count.
+///
+/// The true count lies between `count - error` and `count`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeavyHitter<K> {
+    pub item: K,
+    pub count: u64,
+    pub error: u64,
+}
+
+impl<K> HeavyHitter<K> {
+    /// Lower bound on the true count of the item.
+    pub fn guaranteed(&self) -> u64 {
+        self.count - self.error
+    }
+}
+
+/// Space-Saving summary of the most frequent items of a stream.
+///
+/// At most `capacity` items are monitored. When a new item arrives and the summary is full it
+/// replaces the item with the smallest count, inheriting that count as its error. Every item
+/// whose true count exceeds `len / capacity` is guaranteed to be monitored. The monitored
+/// items also form a binary min-heap on their counts, so finding the item to evict takes O(1)
+/// and every update O(log capacity).
+#[derive(Clone, Debug)]
+pub struct SpaceSaving<K: Hash + Eq + Clone> {
+    capacity: usize,
+    // Count, error and heap slot of each monitored item
+    counters: HashMap<K, (u64, u64, usize)>,
+    heap: Vec<K>,
+    total: u64,
+}
+
+impl<K: Hash + Eq + Clone> SpaceSaving<K> {
+    pub fn new(capacity: usize) -> Result<Self, Error> {
+        if capacity == 0 {
+            return Err(Error::InvalidParameter("capacity must be positive"));
+        }
+        Ok(SpaceSaving {
+            capacity,
+            counters: HashMap::with_capacity(capacity),
+            heap: Vec::with_capacity(capacity),
+            total: 0,
+        })
+    }
+
+    fn count_at(&self, slot: usize) -> u64 {
+        self.counters[&self.heap[slot]].0
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.counters.get_mut(&self.heap[a]).unwrap().2 = a;
+        self.counters.get_mut(&self.heap[b]).unwrap().2 = b;
+    }
+
+    fn sift_up(&mut self, mut slot: usize) {
+        while slot > 0 && self.count_at(slot) < self.count_at((slot - 1) / 2) {
+            self.swap(slot, (slot - 1) / 2);
+            slot = (slot - 1) / 2;
+        }
+    }
+
+    fn sift_down(&mut self, mut slot: usize) {
+        loop {
+            let smallest = [2 * slot + 1, 2 * slot + 2].into_iter()
+                .filter(|child| *child < self.heap.len())
+                .fold(slot, |min, child| if self.count_at(child) < self.count_at(min) { child } else { min });
+            if smallest == slot {
+                return;
+            }
+            self.swap(slot, smallest);
+            slot = smallest;
+        }
+    }
+
+    pub fn push(&mut self, item: K) {
+        self.push_n(item, 1);
+    }
+
+    pub fn push_n(&mut self, item: K, n: u64) {
+        self.total += n;
+        if let Some((count, _, slot)) = self.counters.get_mut(&item) {
+            *count += n;
+            let slot = *slot;
+            self.sift_down(slot);
+        } else if self.counters.len() < self.capacity {
+            let slot = self.heap.len();
+            self.heap.push(item.clone());
+            self.counters.insert(item, (n, 0, slot));
+            self.sift_up(slot);
+        } else {
+            let evicted = std::mem::replace(&mut self.heap[0], item.clone());
+            let (min, _, _) = self.counters.remove(&evicted).unwrap();
+            self.counters.insert(item, (min + n, min, 0));
+            self.sift_down(0);
+        }
+    }
+
+    pub fn push_vec(&mut self, v: Vec<K>) {
+        v.into_iter().for_each(|item| self.push(item));
+    }
+
+    // Count an unmonitored item may have had without being tracked.
+    fn floor(&self) -> u64 {
+        if self.counters.len() < self.capacity {
+            0
+        } else {
+            self.count_at(0)
+        }
+    }
+
+    /// Combines two summaries so that the result bounds the counts of the concatenated streams.
+    /// Items missing from one side are charged that side's smallest count as error.
+    pub fn merge(&mut self, other: &SpaceSaving<K>) {
+        let (ours, theirs) = (self.floor(), other.floor());
+        let mut merged: HashMap<K, (u64, u64)> = HashMap::new();
+        for (item, (count, error, _)) in self.counters.iter() {
+            let (c, e) = other.counters.get(item).map_or((theirs, theirs), |(c, e, _)| (*c, *e));
+            merged.insert(item.clone(), (count + c, error + e));
+        }
+        for (item, (count, error, _)) in other.counters.iter() {
+            if !merged.contains_key(item) {
+                merged.insert(item.clone(), (count + ours, error + ours));
+            }
+        }
+        let mut entries: Vec<(K, (u64, u64))> = merged.into_iter().collect();
+        entries.sort_by_key(|(_, (count, _))| std::cmp::Reverse(*count));
+        entries.truncate(self.capacity);
+
+        // Counts in decreasing order, so reversing them gives a valid min-heap.
+        self.heap = entries.iter().rev().map(|(item, _)| item.clone()).collect();
+        self.counters = entries.into_iter().rev().enumerate()
+            .map(|(slot, (item, (count, error)))| (item, (count, error, slot)))
+            .collect();
+        self.total += other.total;
+    }
+
+    pub fn reset(&mut self) {
+        self.counters.clear();
+        self.heap.clear();
+        self.total = 0;
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Total weight pushed into the summary.
+    pub fn len(&self) -> u64 {
+        self.total
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// Estimate for a monitored item, or `None` if it is not currently tracked.
+    pub fn get(&self, item: &K) -> Option<HeavyHitter<K>> {
+        self.counters.get(item).map(|(count, error, _)| HeavyHitter {
+            item: item.clone(),
+            count: *count,
+            error: *error,
+        })
+    }
+
+    /// Upper bound on the count of any item, tracked or not.
+    pub fn upper_bound(&self, item: &K) -> u64 {
+        self.counters.get(item).map_or_else(|| self.floor(), |(count, _, _)| *count)
+    }
+
+    /// The `n` items with the largest estimated counts, in decreasing order.
+    pub fn top(&self, n: usize) -> Vec<HeavyHitter<K>> {
+        let mut top: Vec<HeavyHitter<K>> = self.counters.iter()
+            .map(|(item, (count, error, _))| HeavyHitter {
+                item: item.clone(),
+                count: *count,
+                error: *error,
+            })
+            .collect();
+        top.sort_by(|a, b| b.count.cmp(&a.count).then(a.error.cmp(&b.error)));
+        top.truncate(n);
+        top
+    }
+
+    /// Items whose count is guaranteed to exceed `fraction` of the stream.
+    pub fn heavy_hitters(&self, fraction: f64) -> Vec<HeavyHitter<K>> {
+        let threshold = fraction * self.total as f64;
+        self.top(self.capacity).into_iter()
+            .filter(|h| h.guaranteed() as f64 > threshold)
+            .collect()
+    }
+}
+
+/// Count-Min sketch of item frequencies.
+///
+/// Estimates never undercount, and overcount by at most `epsilon * len` with probability
+/// `1 - delta`, where `epsilon = e / width` and `delta = exp(-depth)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    counters: Vec<u64>,
+    total: u64,
+}
+
+impl CountMinSketch {
+    pub fn new(width: usize, depth: usize) -> Result<Self, Error> {
+        if width == 0 || depth == 0 {
+            return Err(Error::InvalidParameter("width and depth must be positive"));
+        }
+        let cells = width.checked_mul(depth)
+            .ok_or(Error::InvalidParameter("width times depth overflows usize"))?;
+        Ok(CountMinSketch {
+            width,
+            depth,
+            counters: vec![0; cells],
+            total: 0,
+        })
+    }
+
+    /// Sketch sized for an additive error of `epsilon * len` with probability `1 - delta`.
+    pub fn with_error(epsilon: f64, delta: f64) -> Result<Self, Error> {
+        if !epsilon.is_finite() || epsilon <= 0.0 || !delta.is_finite() || delta <= 0.0 || delta >= 1.0 {
+            return Err(Error::InvalidParameter("epsilon must be positive and delta between 0 and 1"));
+        }
+        let width = f64::ceil(f64::consts::E / epsilon);
+        let depth = f64::ceil(f64::ln(1.0 / delta));
+        if width * depth >= usize::MAX as f64 {
+            return Err(Error::InvalidParameter("epsilon and delta need a sketch larger than usize"));
+        }
+        CountMinSketch::new(width as usize, depth as usize)
+    }
+
+    fn cell(&self, row: usize, h: u64) -> usize {
+        row * self.width + (h % self.width as u64) as usize
+    }
+
+    pub fn push<H: Hash + ?Sized>(&mut self, item: &H) {
+        self.push_n(item, 1);
+    }
+
+    pub fn push_n<H: Hash + ?Sized>(&mut self, item: &H, n: u64) {
+        for row in 0..self.depth {
+            let cell = self.cell(row, hash(item, row as u64));
+            self.counters[cell] += n;
+        }
+        self.total += n;
+    }
+
+    pub fn estimate<H: Hash + ?Sized>(&self, item: &H) -> u64 {
+        (0..self.depth)
+            .map(|row| self.counters[self.cell(row, hash(item, row as u64))])
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn merge(&mut self, other: &CountMinSketch) -> Result<(), Error> {
+        if self.width != other.width || self.depth != other.depth {
+            return Err(Error::InvalidParameter("cannot merge sketches of different dimensions"));
+        }
+        self.counters.iter_mut().zip(other.counters.iter()).for_each(|(a, b)| *a += b);
+        self.total += other.total;
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        self.counters.iter_mut().for_each(|c| *c = 0);
+        self.total = 0;
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Relative error bound, so estimates exceed the true count by at most `epsilon * len`.
+    pub fn epsilon(&self) -> f64 {
+        f64::consts::E / self.width as f64
+    }
+
+    pub fn len(&self) -> u64 {
+        self.total
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CountMinSketch, SpaceSaving};
+
+    // Zipf-like stream where item i appears roughly 1000 / (i + 1) times.
+    fn stream() -> Vec<u32> {
+        let mut vs = vec![];
+        for i in 0..200u32 {
+            (0..1000 / (i + 1)).for_each(|_| vs.push(i));
+        }
+        let mut state = 17u64;
+        for i in (1..vs.len()).rev() {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            vs.swap(i, (state >> 33) as usize % (i + 1));
+        }
+        vs
+    }
+
+    fn true_count(vs: &[u32], item: u32) -> u64 {
+        vs.iter().filter(|v| **v == item).count() as u64
+    }
+
+    #[test]
+    fn space_saving_test() {
+        let vs = stream();
+        let mut ss = SpaceSaving::new(20).unwrap();
+        ss.push_vec(vs.clone());
+
+        assert_eq!(ss.len(), vs.len() as u64);
+        let top = ss.top(3);
+        assert_eq!(top.iter().map(|h| h.item).collect::<Vec<u32>>(), vec![0, 1, 2]);
+        for h in ss.top(20) {
+            let n = true_count(&vs, h.item);
+            assert!(h.guaranteed() <= n && n <= h.count);
+        }
+        assert!(ss.upper_bound(&199) >= true_count(&vs, 199));
+        assert_eq!(ss.heavy_hitters(0.1).iter().map(|h| h.item).collect::<Vec<u32>>(), vec![0]);
+        assert!(SpaceSaving::<u32>::new(0).is_err());
+    }
+
+    #[test]
+    fn space_saving_heap_test() {
+        // The root of the heap is always the smallest monitored count.
+        let mut ss = SpaceSaving::new(8).unwrap();
+        let mut other = SpaceSaving::new(8).unwrap();
+        for (i, v) in stream().iter().enumerate() {
+            ss.push_n(*v, 1 + (i % 3) as u64);
+            other.push(v / 2);
+            let min = ss.counters.values().map(|(count, _, _)| *count).min().unwrap();
+            assert_eq!(ss.floor(), if ss.counters.len() < 8 { 0 } else { min });
+        }
+        ss.merge(&other);
+        let min = ss.counters.values().map(|(count, _, _)| *count).min().unwrap();
+        assert_eq!(ss.floor(), min);
+        ss.counters.iter().for_each(|(item, (_, _, slot))| assert_eq!(&ss.heap[*slot], item));
+    }
+
+    #[test]
+    fn space_saving_merge_test() {
+        let vs = stream();
+        let (left, right) = vs.split_at(vs.len() / 3);
+        let mut a = SpaceSaving::new(20).unwrap();
+        let mut b = SpaceSaving::new(20).unwrap();
+        a.push_vec(left.to_vec());
+        b.push_vec(right.to_vec());
+        a.merge(&b);
+
+        assert_eq!(a.len(), vs.len() as u64);
+        assert_eq!(a.top(20).len(), 20);
+        assert_eq!(a.top(1)[0].item, 0);
+        for h in a.top(20) {
+            let n = true_count(&vs, h.item);
+            assert!(h.guaranteed() <= n && n <= h.count);
+        }
+    }
+
+    #[test]
+    fn count_min_test() {
+        assert!(CountMinSketch::new(0, 4).is_err());
+        assert!(CountMinSketch::with_error(0.01, 1.0).is_err());
+        assert!(CountMinSketch::with_error(1e-30, 0.01).is_err());
+        assert!(CountMinSketch::with_error(f64::INFINITY, 0.01).is_err());
+        assert!(CountMinSketch::with_error(-0.01, 0.01).is_err());
+        assert!(CountMinSketch::with_error(0.01, f64::NAN).is_err());
+        assert!(CountMinSketch::new(usize::MAX / 2, 3).is_err());
+
+        let vs = stream();
+        let mut a = CountMinSketch::with_error(0.001, 0.01).unwrap();
+        let mut b = a.clone();
+        let (left, right) = vs.split_at(vs.len() / 2);
+        left.iter().for_each(|v| a.push(v));
+        right.iter().for_each(|v| b.push(v));
+        a.merge(&b).unwrap();
+
+        assert_eq!(a.depth(), 5);
+        assert_eq!(a.len(), vs.len() as u64);
+        let bound = (a.epsilon() * a.len() as f64) as u64;
+        for item in [0, 1, 50, 199, 1000].iter() {
+            let n = true_count(&vs, *item);
+            let estimate = a.estimate(item);
+            assert!(n <= estimate && estimate <= n + bound);
+        }
+        assert!(a.merge(&CountMinSketch::new(10, 5).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use crate::accumulator::Accumulator;
+use crate::error::Error;
+
+/// Histogram over fixed bin edges with incremental counts.
+///
+/// Bin `i` covers `[edges[i], edges[i + 1])`, except the last bin which also includes its upper
+/// edge. Values outside the edges are tallied as underflow or overflow. Values can be removed
+/// again, so the histogram can follow a rolling window.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    edges: Vec<f64>,
+    counts: Vec<usize>,
+    underflow: usize,
+    overflow: usize,
+}
+
+impl Histogram {
+    pub fn from_edges(edges: Vec<f64>) -> Result<Self, Error> {
+        if edges.len() < 2 {
+            return Err(Error::InvalidParameter("a histogram needs at least two edges"));
+        }
+        if edges.iter().any(|e| !e.is_finite()) || edges.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(Error::InvalidParameter("edges must be finite and strictly increasing"));
+        }
+        let bins = edges.len() - 1;
+        Ok(Histogram {
+            edges,
+            counts: vec![0; bins],
+            underflow: 0,
+            overflow: 0,
+        })
+    }
+
+    pub fn uniform(low: f64, high: f64, bins: usize) -> Result<Self, Error> {
+        if bins == 0 {
+            return Err(Error::InvalidParameter("a histogram needs at least one bin"));
+        }
+        let width = (high - low) / bins as f64;
+        let mut edges: Vec<f64> = (0..bins).map(|i| low + width * i as f64).collect();
+        edges.push(high);
+        Histogram::from_edges(edges)
+    }
+
+    pub fn log(low: f64, high: f64, bins: usize) -> Result<Self, Error> {
+        if bins == 0 {
+            return Err(Error::InvalidParameter("a histogram needs at least one bin"));
+        }
+        if low.is_nan() || low <= 0.0 {
+            return Err(Error::InvalidParameter("logarithmic bins need a positive lower edge"));
+        }
+        let ratio = f64::ln(high / low) / bins as f64;
+        let mut edges: Vec<f64> = (0..bins).map(|i| low * f64::exp(ratio * i as f64)).collect();
+        edges.push(high);
+        Histogram::from_edges(edges)
+    }
+
+    // Ok with the bin index for values inside the edges, Err(true) for underflow and
+    // Err(false) for overflow.
+    fn bin(&self, x: f64) -> Result<usize, bool> {
+        let last = self.edges.len() - 1;
+        if x < self.edges[0] {
+            Err(true)
+        } else if x == self.edges[last] {
+            Ok(last - 1)
+        } else if x > self.edges[last] || x.is_nan() {
+            Err(false)
+        } else {
+            Ok(self.edges.partition_point(|e| *e <= x) - 1)
+        }
+    }
+
+    pub fn push(&mut self, x: f64) {
+        match self.bin(x) {
+            Ok(i) => self.counts[i] += 1,
+            Err(true) => self.underflow += 1,
+            Err(false) => self.overflow += 1,
+        }
+    }
+
+    pub fn push_vec(&mut self, v: Vec<f64>) {
+        v.iter().for_each(|x| self.push(*x));
+    }
+
+    /// Removes one occurrence of `x`, returning false if its bin is already empty.
+    pub fn remove(&mut self, x: f64) -> bool {
+        let count = match self.bin(x) {
+            Ok(i) => &mut self.counts[i],
+            Err(true) => &mut self.underflow,
+            Err(false) => &mut self.overflow,
+        };
+        if *count > 0 {
+            *count -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.counts.iter_mut().for_each(|c| *c = 0);
+        self.underflow = 0;
+        self.overflow = 0;
+    }
+
+    pub fn edges(&self) -> &[f64] {
+        &self.edges
+    }
+
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+
+    pub fn underflow(&self) -> usize {
+        self.underflow
+    }
+
+    pub fn overflow(&self) -> usize {
+        self.overflow
+    }
+
+    pub fn bins(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Number of values recorded, including underflow and overflow.
+    pub fn len(&self) -> usize {
+        self.in_range() + self.underflow + self.overflow
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn in_range(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    /// Counts divided by the number of values inside the edges and the bin width, so that the
+    /// density integrates to one over the range of the histogram.
+    pub fn density(&self) -> Option<Vec<f64>> {
+        let total = self.in_range();
+        if total == 0 {
+            return None;
+        }
+        Some(self.counts.iter().zip(self.edges.windows(2))
+            .map(|(c, e)| *c as f64 / (total as f64 * (e[1] - e[0])))
+            .collect())
+    }
+
+    /// Number of values below the upper edge of each bin, including the underflow.
+    pub fn cumulative(&self) -> Vec<usize> {
+        self.counts.iter()
+            .scan(self.underflow, |total, c| {
+                *total += c;
+                Some(*total)
+            })
+            .collect()
+    }
+}
+
+impl Accumulator for Histogram {
+    fn push(&mut self, x: f64) {
+        Histogram::push(self, x);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+    use super::Histogram;
+    use crate::fstats_f64::Stats;
+
+    #[test]
+    fn edges_test() {
+        assert!(Histogram::from_edges(vec![1.0]).is_err());
+        assert!(Histogram::from_edges(vec![1.0, 1.0]).is_err());
+        assert!(Histogram::uniform(0.0, 1.0, 0).is_err());
+        assert!(Histogram::log(0.0, 1.0, 4).is_err());
+
+        let h = Histogram::log(1.0, 1000.0, 3).unwrap();
+        assert_eq!(h.bins(), 3);
+        assert!( approx_eq!(f64, h.edges()[1], 10.0, epsilon = 1e-12) );
+        assert!( approx_eq!(f64, h.edges()[2], 100.0, epsilon = 1e-12) );
+    }
+
+    #[test]
+    fn push_test() {
+        let mut h = Histogram::uniform(0.0, 10.0, 5).unwrap();
+        h.push_vec(vec![-1.0, 0.0, 1.9, 2.0, 5.5, 10.0, 10.5, 3.0]);
+
+        assert_eq!(h.counts(), &[2, 2, 1, 0, 1]);
+        assert_eq!(h.underflow(), 1);
+        assert_eq!(h.overflow(), 1);
+        assert_eq!(h.len(), 8);
+        assert_eq!(h.cumulative(), vec![3, 5, 6, 6, 7]);
+    }
+
+    #[test]
+    fn density_test() {
+        let mut h = Histogram::from_edges(vec![0.0, 1.0, 3.0]).unwrap();
+        assert_eq!(h.density(), None);
+
+        h.push_vec(vec![0.5, 1.5, 2.5, 2.0]);
+        let density = h.density().unwrap();
+        let integral: f64 = density.iter().zip([1.0, 2.0].iter()).map(|(d, w)| d * w).sum();
+        assert!( approx_eq!(f64, density[0], 0.25, epsilon = 1e-15) );
+        assert!( approx_eq!(f64, integral, 1.0, epsilon = 1e-15) );
+    }
+
+    #[test]
+    fn rolling_test() {
+        let window = 4;
+        let vs = [1.0, 7.0, 3.0, 9.0, 2.0, 8.0, 4.0];
+        let mut h = Histogram::uniform(0.0, 10.0, 2).unwrap();
+        let mut vfs = Stats::new();
+        for x in vs.iter() {
+            vfs.push(*x);
+            h.push(*x);
+            if vfs.len() > window {
+                let old = vfs.remove(0);
+                assert!(h.remove(old));
+            }
+        }
+
+        assert_eq!(h.counts(), &[2, 2]);
+        assert_eq!(h.len(), vfs.len());
+        assert!(!h.remove(100.0));
+    }
+}
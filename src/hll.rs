@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::f64;
+use std::hash::Hash;
+
+use crate::accumulator::Accumulator;
+use crate::error::Error;
+use crate::hash::{hash, hash_f64};
+
+const SPARSE_PRECISION: u32 = 25;
+const SPARSE: u8 = 0;
+const DENSE: u8 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Registers {
+    // Index at the sparse precision mapped to the largest rank seen for it
+    Sparse(HashMap<u32, u8>),
+    Dense(Vec<u8>),
+}
+
+/// HyperLogLog++ estimator of the number of distinct items in a stream.
+///
+/// Small cardinalities are kept in a sparse representation at a precision of 25 bits and
+/// estimated by linear counting; once that grows past the size of the dense registers it
+/// switches to `2^precision` registers. The dense estimate uses Ertl's improved estimator,
+/// which removes the bias of the raw estimate without empirical correction tables.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HyperLogLog {
+    precision: u32,
+    registers: Registers,
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if z == previous {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x) * (1.0 - x) * y;
+        if z == previous {
+            return z / 3.0;
+        }
+    }
+}
+
+impl HyperLogLog {
+    pub fn new(precision: u32) -> Result<Self, Error> {
+        if !(4..=18).contains(&precision) {
+            return Err(Error::InvalidParameter("precision must be between 4 and 18"));
+        }
+        Ok(HyperLogLog {
+            precision,
+            registers: Registers::Sparse(HashMap::new()),
+        })
+    }
+
+    fn dense_index(&self, sparse_index: u32, rank: u8) -> (usize, u8) {
+        let extra = SPARSE_PRECISION - self.precision;
+        let index = (sparse_index >> extra) as usize;
+        let bits = sparse_index & ((1 << extra) - 1);
+        if bits != 0 {
+            (index, (bits.leading_zeros() - (32 - extra) + 1) as u8)
+        } else {
+            (index, rank + extra as u8)
+        }
+    }
+
+    fn densify(&mut self) {
+        if let Registers::Sparse(sparse) = &self.registers {
+            let mut dense = vec![0; 1 << self.precision];
+            for (sparse_index, rank) in sparse.iter() {
+                let (index, rank) = self.dense_index(*sparse_index, *rank);
+                dense[index] = u8::max(dense[index], rank);
+            }
+            self.registers = Registers::Dense(dense);
+        }
+    }
+
+    fn add_hash(&mut self, h: u64) {
+        match &mut self.registers {
+            Registers::Sparse(sparse) => {
+                let index = (h >> (64 - SPARSE_PRECISION)) as u32;
+                let rank = ((h << SPARSE_PRECISION) | (1 << (SPARSE_PRECISION - 1))).leading_zeros() as u8 + 1;
+                let entry = sparse.entry(index).or_insert(0);
+                *entry = u8::max(*entry, rank);
+                if sparse.len() > 1 << (self.precision - 2) {
+                    self.densify();
+                }
+            }
+            Registers::Dense(dense) => {
+                let index = (h >> (64 - self.precision)) as usize;
+                let rank = ((h << self.precision) | (1 << (self.precision - 1))).leading_zeros() as u8 + 1;
+                dense[index] = u8::max(dense[index], rank);
+            }
+        }
+    }
+
+    pub fn push<H: Hash + ?Sized>(&mut self, item: &H) {
+        self.add_hash(hash(item, 0));
+    }
+
+    pub fn push_f64(&mut self, x: f64) {
+        self.add_hash(hash_f64(x, 0));
+    }
+
+    pub fn merge(&mut self, other: &HyperLogLog) -> Result<(), Error> {
+        if self.precision != other.precision {
+            return Err(Error::InvalidParameter("cannot merge sketches of different precision"));
+        }
+        match &other.registers {
+            Registers::Sparse(theirs) => {
+                if let Registers::Sparse(ours) = &mut self.registers {
+                    for (index, rank) in theirs.iter() {
+                        let entry = ours.entry(*index).or_insert(0);
+                        *entry = u8::max(*entry, *rank);
+                    }
+                    if ours.len() > 1 << (self.precision - 2) {
+                        self.densify();
+                    }
+                } else {
+                    let updates: Vec<(usize, u8)> = theirs.iter()
+                        .map(|(index, rank)| self.dense_index(*index, *rank))
+                        .collect();
+                    if let Registers::Dense(ours) = &mut self.registers {
+                        for (index, rank) in updates {
+                            ours[index] = u8::max(ours[index], rank);
+                        }
+                    }
+                }
+            }
+            Registers::Dense(theirs) => {
+                self.densify();
+                if let Registers::Dense(ours) = &mut self.registers {
+                    for (r, t) in ours.iter_mut().zip(theirs.iter()) {
+                        *r = u8::max(*r, *t);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        self.registers = Registers::Sparse(HashMap::new());
+    }
+
+    pub fn precision(&self) -> u32 {
+        self.precision
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match &self.registers {
+            Registers::Sparse(sparse) => sparse.is_empty(),
+            Registers::Dense(dense) => dense.iter().all(|r| *r == 0),
+        }
+    }
+
+    /// Standard error of the dense estimate relative to the true cardinality.
+    pub fn relative_error(&self) -> f64 {
+        1.04 / f64::sqrt((1u64 << self.precision) as f64)
+    }
+
+    pub fn estimate(&self) -> f64 {
+        match &self.registers {
+            Registers::Sparse(sparse) => {
+                let m = (1u64 << SPARSE_PRECISION) as f64;
+                m * f64::ln(m / (m - sparse.len() as f64))
+            }
+            Registers::Dense(dense) => {
+                let m = dense.len() as f64;
+                let q = 64 - self.precision as usize;
+                let mut histogram = vec![0.0; q + 2];
+                dense.iter().for_each(|r| histogram[*r as usize] += 1.0);
+                let mut z = m * tau(1.0 - histogram[q + 1] / m);
+                for k in (1..=q).rev() {
+                    z = 0.5 * (z + histogram[k]);
+                }
+                z += m * sigma(histogram[0] / m);
+                m * m / (2.0 * f64::ln(2.0) * z)
+            }
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.estimate().round() as u64
+    }
+
+    /// Encodes a format byte and the precision, followed by either the sorted sparse entries
+    /// or the dense registers.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match &self.registers {
+            Registers::Sparse(sparse) => {
+                let mut entries: Vec<(&u32, &u8)> = sparse.iter().collect();
+                entries.sort();
+                let mut bytes = vec![SPARSE, self.precision as u8];
+                for (index, rank) in entries {
+                    bytes.extend_from_slice(&index.to_le_bytes());
+                    bytes.push(*rank);
+                }
+                bytes
+            }
+            Registers::Dense(dense) => {
+                let mut bytes = vec![DENSE, self.precision as u8];
+                bytes.extend_from_slice(dense);
+                bytes
+            }
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 2 {
+            return Err(Error::InvalidEncoding);
+        }
+        let mut hll = HyperLogLog::new(bytes[1] as u32)?;
+        let body = &bytes[2..];
+        match bytes[0] {
+            SPARSE if body.len().is_multiple_of(5) => {
+                let sparse: HashMap<u32, u8> = body.chunks_exact(5)
+                    .map(|c| (u32::from_le_bytes([c[0], c[1], c[2], c[3]]), c[4]))
+                    .collect();
+                let max_rank = (65 - SPARSE_PRECISION) as u8;
+                if sparse.iter().any(|(i, r)| *i >> SPARSE_PRECISION != 0 || *r > max_rank) {
+                    return Err(Error::InvalidEncoding);
+                }
+                hll.registers = Registers::Sparse(sparse);
+            }
+            DENSE if body.len() == 1 << hll.precision => {
+                if body.iter().any(|r| *r as u32 > 65 - hll.precision) {
+                    return Err(Error::InvalidEncoding);
+                }
+                hll.registers = Registers::Dense(body.to_vec());
+            }
+            _ => return Err(Error::InvalidEncoding),
+        }
+        Ok(hll)
+    }
+}
+
+impl Accumulator for HyperLogLog {
+    fn push(&mut self, x: f64) {
+        self.push_f64(x);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HyperLogLog;
+    use crate::error::Error;
+
+    fn within(hll: &HyperLogLog, n: usize, tolerance: f64) -> bool {
+        (hll.estimate() - n as f64).abs() <= tolerance * n as f64
+    }
+
+    #[test]
+    fn precision_test() {
+        assert!(HyperLogLog::new(3).is_err());
+        assert!(HyperLogLog::new(19).is_err());
+        assert!(HyperLogLog::new(14).unwrap().is_empty());
+    }
+
+    #[test]
+    fn small_cardinality_test() {
+        let mut hll = HyperLogLog::new(14).unwrap();
+        for _ in 0..3 {
+            (0..500).for_each(|i| hll.push(&i));
+        }
+
+        assert!(within(&hll, 500, 0.01));
+        assert_eq!(HyperLogLog::new(14).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn large_cardinality_test() {
+        let mut hll = HyperLogLog::new(14).unwrap();
+        (0..200_000u64).for_each(|i| hll.push(&i));
+        (0..50_000).for_each(|i| hll.push_f64(i as f64 + 0.5));
+
+        assert!(within(&hll, 250_000, 3.0 * hll.relative_error()));
+    }
+
+    #[test]
+    fn merge_test() {
+        let mut a = HyperLogLog::new(12).unwrap();
+        let mut b = HyperLogLog::new(12).unwrap();
+        let mut c = HyperLogLog::new(12).unwrap();
+        (0..30_000).for_each(|i| a.push(&format!("key-{}", i)));
+        (20_000..50_000).for_each(|i| b.push(&format!("key-{}", i)));
+        (0..100).for_each(|i| c.push(&format!("key-{}", i)));
+
+        let mut union = a.clone();
+        union.merge(&b).unwrap();
+        union.merge(&c).unwrap();
+        assert!(within(&union, 50_000, 3.0 * union.relative_error()));
+
+        c.merge(&a).unwrap();
+        assert_eq!(c, a);
+        assert_eq!(a.merge(&HyperLogLog::new(10).unwrap()), Err(Error::InvalidParameter("cannot merge sketches of different precision")));
+    }
+
+    #[test]
+    fn bytes_test() {
+        let mut sparse = HyperLogLog::new(10).unwrap();
+        (0..100).for_each(|i| sparse.push(&i));
+        let mut dense = sparse.clone();
+        (0..10_000).for_each(|i| dense.push(&i));
+
+        assert_eq!(HyperLogLog::from_bytes(&sparse.to_bytes()), Ok(sparse.clone()));
+        assert_eq!(HyperLogLog::from_bytes(&dense.to_bytes()), Ok(dense.clone()));
+        assert_eq!(dense.to_bytes().len(), 2 + 1024);
+        assert_eq!(HyperLogLog::from_bytes(&dense.to_bytes()[..100]), Err(Error::InvalidEncoding));
+    }
+}
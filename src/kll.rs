@@ -0,0 +1,297 @@
+use std::f64;
+
+use crate::accumulator::Accumulator;
+use crate::error::Error;
+use crate::quantile::QuantileSketch;
+use crate::rng::Rng;
+
+const MIN_CAPACITY: usize = 8;
+const CAPACITY_DECAY: f64 = 2.0 / 3.0;
+const DEFAULT_SEED: u64 = 0x5eed_6b11;
+
+/// KLL quantile sketch with a bounded rank error.
+///
+/// Items are kept in a hierarchy of compactors where an item at level `h` stands for `2^h`
+/// items of the stream. When a compactor fills up it is sorted and every other item, starting
+/// from a random offset, is promoted to the next level. With `k` the size of the top compactor,
+/// the normalized rank error of any single query stays below `epsilon()` with 99% probability.
+#[derive(Clone, Debug)]
+pub struct Kll {
+    k: usize,
+    compactors: Vec<Vec<f64>>,
+    length: usize,
+    max: Option<f64>,
+    min: Option<f64>,
+    rng: Rng,
+}
+
+impl Default for Kll {
+    fn default() -> Self {
+        Kll::with_capacity(200, DEFAULT_SEED)
+    }
+}
+
+impl Kll {
+    pub fn new(k: usize) -> Result<Self, Error> {
+        Kll::with_seed(k, DEFAULT_SEED)
+    }
+
+    /// The size `k` of the top compactor must be at least 8.
+    pub fn with_seed(k: usize, seed: u64) -> Result<Self, Error> {
+        if k < MIN_CAPACITY {
+            return Err(Error::InvalidParameter("k must be at least 8"));
+        }
+        Ok(Kll::with_capacity(k, seed))
+    }
+
+    fn with_capacity(k: usize, seed: u64) -> Self {
+        Kll {
+            k,
+            compactors: vec![vec![]],
+            length: 0,
+            max: None,
+            min: None,
+            rng: Rng::new(seed),
+        }
+    }
+
+    fn capacity(&self, level: usize) -> usize {
+        let depth = self.compactors.len() - level - 1;
+        let capacity = (self.k as f64 * CAPACITY_DECAY.powi(depth as i32)).ceil() as usize;
+        usize::max(MIN_CAPACITY, capacity)
+    }
+
+    fn max_size(&self) -> usize {
+        (0..self.compactors.len()).map(|h| self.capacity(h)).sum()
+    }
+
+    fn compress(&mut self) {
+        while self.num_retained() >= self.max_size() {
+            let level = (0..self.compactors.len())
+                .find(|&h| self.compactors[h].len() >= self.capacity(h))
+                .unwrap();
+            if level + 1 == self.compactors.len() {
+                self.compactors.push(vec![]);
+            }
+
+            let mut items = std::mem::take(&mut self.compactors[level]);
+            items.sort_by(f64::total_cmp);
+            if items.len() % 2 == 1 {
+                let x = items.pop().unwrap();
+                self.compactors[level].push(x);
+            }
+            let offset = self.rng.next_bool() as usize;
+            let promoted = items.into_iter().skip(offset).step_by(2);
+            self.compactors[level + 1].extend(promoted);
+        }
+    }
+
+    fn weighted(&self) -> Vec<(f64, usize)> {
+        let mut items: Vec<(f64, usize)> = self.compactors.iter().enumerate()
+            .flat_map(|(h, c)| c.iter().map(move |x| (*x, 1 << h)))
+            .collect();
+        items.sort_by(|a, b| a.0.total_cmp(&b.0));
+        items
+    }
+
+    pub fn push(&mut self, x: f64) {
+        if self.max.is_none() || self.max < Some(x) {
+            self.max = Some(x)
+        };
+        if self.min.is_none() || self.min > Some(x) {
+            self.min = Some(x)
+        };
+        self.compactors[0].push(x);
+        self.length += 1;
+        if self.num_retained() >= self.max_size() {
+            self.compress();
+        }
+    }
+
+    pub fn push_vec(&mut self, v: Vec<f64>) {
+        v.iter().for_each(|x| self.push(*x));
+    }
+
+    pub fn merge(&mut self, other: &Kll) {
+        if other.length == 0 {
+            return;
+        }
+        if self.max.is_none() || self.max < other.max {
+            self.max = other.max
+        };
+        if self.min.is_none() || self.min > other.min {
+            self.min = other.min
+        };
+        while self.compactors.len() < other.compactors.len() {
+            self.compactors.push(vec![]);
+        }
+        for (h, c) in other.compactors.iter().enumerate() {
+            self.compactors[h].extend_from_slice(c);
+        }
+        self.length += other.length;
+        self.compress();
+    }
+
+    pub fn reset(&mut self) {
+        self.compactors = vec![vec![]];
+        self.length = 0;
+        self.max = None;
+        self.min = None;
+    }
+
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Bound on the normalized rank error of a single query, holding with 99% probability.
+    pub fn epsilon(&self) -> f64 {
+        2.296 / f64::powf(self.k as f64, 0.9723)
+    }
+
+    pub fn num_retained(&self) -> usize {
+        self.compactors.iter().map(|c| c.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        self.min
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        self.max
+    }
+
+    /// Estimated fraction of the stream less than or equal to `x`.
+    pub fn rank(&self, x: f64) -> Option<f64> {
+        if self.length == 0 {
+            return None;
+        }
+        let count: usize = self.compactors.iter().enumerate()
+            .map(|(h, c)| c.iter().filter(|y| **y <= x).count() << h)
+            .sum();
+        Some(count as f64 / self.length as f64)
+    }
+
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.length == 0 || !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+        if q == 0.0 {
+            return self.min;
+        }
+        if q == 1.0 {
+            return self.max;
+        }
+        let target = q * self.length as f64;
+        let mut weight_so_far = 0;
+        for (x, weight) in self.weighted() {
+            weight_so_far += weight;
+            if weight_so_far as f64 >= target {
+                return Some(x);
+            }
+        }
+        self.max
+    }
+}
+
+impl Accumulator for Kll {
+    fn push(&mut self, x: f64) {
+        Kll::push(self, x);
+    }
+}
+
+impl QuantileSketch for Kll {
+    fn merge(&mut self, other: &Self) {
+        Kll::merge(self, other);
+    }
+
+    fn quantile(&mut self, q: f64) -> Option<f64> {
+        Kll::quantile(self, q)
+    }
+
+    fn rank(&mut self, x: f64) -> Option<f64> {
+        Kll::rank(self, x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Kll;
+    use crate::error::Error;
+    use crate::test_util::values;
+    use crate::quantile::QuantileSketch;
+    use crate::tdigest::TDigest;
+
+    // Exact normalized rank of x within the sorted values
+    fn exact_rank(sorted: &[f64], x: f64) -> f64 {
+        sorted.partition_point(|y| *y <= x) as f64 / sorted.len() as f64
+    }
+
+    #[test]
+    fn rank_error_test() {
+        let vs = values(100000, 1);
+        let mut sorted = vs.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let mut kll = Kll::new(200).unwrap();
+        kll.push_vec(vs.clone());
+
+        let eps = kll.epsilon();
+        assert!(kll.num_retained() < 1000);
+        for q in [0.01, 0.1, 0.25, 0.5, 0.75, 0.9, 0.99].iter() {
+            let x = kll.quantile(*q).unwrap();
+            assert!((exact_rank(&sorted, x) - q).abs() <= eps, "q = {}", q);
+            let rank = kll.rank(sorted[(q * vs.len() as f64) as usize]).unwrap();
+            assert!((rank - q).abs() <= eps, "q = {}", q);
+        }
+        assert_eq!(kll.quantile(0.0), kll.min());
+        assert_eq!(kll.quantile(1.0), kll.max());
+    }
+
+    #[test]
+    fn merge_test() {
+        let vs = values(80000, 2);
+        let mut sorted = vs.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let mut kll = Kll::with_seed(200, 0).unwrap();
+        for (i, chunk) in vs.chunks(20000).enumerate() {
+            let mut shard = Kll::with_seed(200, i as u64 + 1).unwrap();
+            shard.push_vec(chunk.to_vec());
+            kll.merge(&shard);
+        }
+
+        assert_eq!(kll.len(), vs.len());
+        for q in [0.05, 0.5, 0.95].iter() {
+            let x = kll.quantile(*q).unwrap();
+            assert!((exact_rank(&sorted, x) - q).abs() <= kll.epsilon(), "q = {}", q);
+        }
+    }
+
+    #[test]
+    fn epsilon_test() {
+        assert!(Kll::new(400).unwrap().epsilon() < Kll::new(200).unwrap().epsilon());
+        assert!(Kll::new(200).unwrap().epsilon() < 0.015);
+        assert_eq!(Kll::new(7).err(), Some(Error::InvalidParameter("k must be at least 8")));
+        assert!(Kll::new(8).is_ok());
+    }
+
+    #[test]
+    fn backend_test() {
+        fn median<S: QuantileSketch>(sketch: &mut S) -> Option<f64> {
+            (1..=101).for_each(|x| sketch.push(x as f64));
+            sketch.quantile(0.5)
+        }
+
+        assert_eq!(median(&mut Kll::default()), Some(51.0));
+        assert!((median(&mut TDigest::default()).unwrap() - 51.0).abs() <= 1.0);
+        assert_eq!(Kll::default().quantile(0.5), None);
+    }
+}
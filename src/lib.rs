@@ -1,3 +1,31 @@
+pub mod accumulator;
+pub mod autocorr;
+pub mod bivariate;
+pub mod crosscorr;
+pub mod decay;
+pub mod error;
+pub mod expanding;
+mod frequency;
 pub mod fstats_f64;
 pub mod fstats_float;
-pub mod stats;
\ No newline at end of file
+mod hash;
+pub mod hdr;
+pub mod heavy;
+pub mod histogram;
+pub mod hll;
+pub mod kll;
+mod moments;
+pub mod multi_window;
+pub mod multivariate;
+mod order_tree;
+pub mod p2;
+pub mod quantile;
+pub mod rank;
+pub mod reservoir;
+pub mod rls;
+mod rng;
+pub mod rolling;
+pub mod stats;
+pub mod tdigest;
+#[cfg(test)]
+mod test_util;
\ No newline at end of file
@@ -0,0 +1,21 @@
+use num_traits::Float;
+
+// Mean and population variance from the count, sum and sum of squares that Stats and the
+// rolling windows keep as running caches.
+
+pub(crate) fn mean<T: Float>(length: usize, sum: T) -> Option<T> {
+    if length > 0 {
+        Some(sum / T::from(length)?)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn variance<T: Float>(length: usize, sum: T, sum_of_squares: T) -> Option<T> {
+    if length > 0 {
+        let n = T::from(length)?;
+        Some(T::max(T::zero(), n * sum_of_squares - sum * sum) / (n * n))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+use std::f64;
+
+use crate::error::Error;
+use crate::fstats_f64::Stats;
+
+#[derive(Clone, Debug)]
+struct Window {
+    duration: f64,
+    // The values inside the window, oldest first
+    stats: Stats,
+}
+
+/// Rolling statistics over several time windows sharing a single clock.
+///
+/// Each window keeps its values in a `Stats`, which maintains its running sums and extremes,
+/// while the timestamps are stored once for the longest window.
+#[derive(Clone, Debug)]
+pub struct MultiWindow {
+    times: VecDeque<f64>,
+    now: Option<f64>,
+    windows: Vec<Window>,
+}
+
+impl MultiWindow {
+    /// Durations must be finite, positive and distinct.
+    pub fn new(durations: &[f64]) -> Result<Self, Error> {
+        if durations.is_empty() {
+            return Err(Error::InvalidParameter("at least one window is required"));
+        }
+        if durations.iter().any(|d| !d.is_finite() || *d <= 0.0) {
+            return Err(Error::InvalidParameter("durations must be finite and positive"));
+        }
+        if durations.iter().enumerate().any(|(i, d)| durations[..i].contains(d)) {
+            return Err(Error::InvalidParameter("durations must be distinct"));
+        }
+        Ok(MultiWindow {
+            times: VecDeque::new(),
+            now: None,
+            windows: durations.iter().map(|d| Window { duration: *d, stats: Stats::new() }).collect(),
+        })
+    }
+
+    fn expire(&mut self, t: f64) {
+        let times = &self.times;
+        for w in self.windows.iter_mut() {
+            let start = times.len() - w.stats.len();
+            let expired = times.range(start..).take_while(|s| **s <= t - w.duration).count();
+            if expired > 0 {
+                w.stats.drain(..expired);
+            }
+        }
+
+        let longest = self.windows.iter().map(|w| w.stats.len()).max().unwrap_or(0);
+        while self.times.len() > longest {
+            self.times.pop_front();
+        }
+    }
+
+    /// Moves the current time forward, dropping values which have fallen out of each window.
+    pub fn advance(&mut self, t: f64) {
+        let now = self.now.map_or(t, |now| f64::max(now, t));
+        self.now = Some(now);
+        self.expire(now);
+    }
+
+    pub fn push(&mut self, x: f64, t: f64) {
+        self.advance(t);
+        self.times.push_back(t);
+        self.windows.iter_mut().for_each(|w| w.stats.push(x));
+    }
+
+    pub fn reset(&mut self) {
+        self.times.clear();
+        self.now = None;
+        self.windows.iter_mut().for_each(|w| w.stats.reset());
+    }
+
+    pub fn durations(&self) -> Vec<f64> {
+        self.windows.iter().map(|w| w.duration).collect()
+    }
+
+    pub fn len(&self, window: usize) -> usize {
+        self.windows[window].stats.len()
+    }
+
+    pub fn is_empty(&self, window: usize) -> bool {
+        self.windows[window].stats.is_empty()
+    }
+
+    pub fn mean(&mut self, window: usize) -> Option<f64> {
+        self.windows[window].stats.mean()
+    }
+
+    pub fn stddev(&mut self, window: usize) -> Option<f64> {
+        self.windows[window].stats.stddev()
+    }
+
+    pub fn min(&self, window: usize) -> Option<f64> {
+        self.windows[window].stats.min()
+    }
+
+    pub fn max(&self, window: usize) -> Option<f64> {
+        self.windows[window].stats.max()
+    }
+
+    /// Number of values in the window per unit of time.
+    pub fn rate(&self, window: usize) -> f64 {
+        let w = &self.windows[window];
+        w.stats.len() as f64 / w.duration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+    use super::MultiWindow;
+    use crate::error::Error;
+    use crate::stats::{mean, stddev, min, max};
+
+    #[test]
+    fn windows_test() {
+        let mut mw = MultiWindow::new(&[5.0, 20.0]).unwrap();
+        let vs: Vec<f64> = (0..50).map(|i| ((i * 37) % 11) as f64).collect();
+        for (i, x) in vs.iter().enumerate() {
+            mw.push(*x, i as f64);
+        }
+
+        let short = &vs[45..];
+        let long = &vs[30..];
+        assert_eq!(mw.len(0), 5);
+        assert_eq!(mw.len(1), 20);
+        assert!( approx_eq!(f64, mean(short).unwrap(), mw.mean(0).unwrap(), epsilon = 1e-12) );
+        assert!( approx_eq!(f64, stddev(long).unwrap(), mw.stddev(1).unwrap(), epsilon = 1e-12) );
+        assert_eq!(min(short), mw.min(0));
+        assert_eq!(max(short), mw.max(0));
+        assert_eq!(min(long), mw.min(1));
+        assert_eq!(max(long), mw.max(1));
+        assert!( approx_eq!(f64, mw.rate(1), 1.0, epsilon = 1e-12) );
+    }
+
+    #[test]
+    fn advance_test() {
+        let mut mw = MultiWindow::new(&[1.0, 10.0]).unwrap();
+        push_values(&mut mw);
+        mw.advance(5.5);
+
+        assert!(mw.is_empty(0));
+        assert_eq!(mw.mean(0), None);
+        assert_eq!(mw.min(0), None);
+        assert_eq!(mw.len(1), 3);
+        assert_eq!(mw.max(1), Some(3.0));
+
+        mw.advance(20.0);
+        assert!(mw.is_empty(1));
+        assert!(mw.times.is_empty());
+    }
+
+    #[test]
+    fn reset_test() {
+        let mut mw = MultiWindow::new(&[1.0, 10.0]).unwrap();
+        push_values(&mut mw);
+        mw.reset();
+
+        assert_eq!(mw.durations(), vec![1.0, 10.0]);
+        assert!(mw.is_empty(1));
+    }
+
+    #[test]
+    fn durations_test() {
+        for durations in [&[][..], &[0.0], &[5.0, -1.0], &[f64::NAN], &[f64::INFINITY], &[5.0, 20.0, 5.0]].iter() {
+            assert!(matches!(MultiWindow::new(durations), Err(Error::InvalidParameter(_))));
+        }
+        assert!(MultiWindow::new(&[20.0, 5.0]).is_ok());
+    }
+
+    fn push_values(mw: &mut MultiWindow) {
+        mw.push(1.0, 1.0);
+        mw.push(3.0, 2.0);
+        mw.push(2.0, 3.0);
+    }
+}
@@ -0,0 +1,264 @@
+use std::f64;
+
+use crate::error::Error;
+
+/// Running mean and covariance of a stream of fixed-length vectors.
+///
+/// Each push is a Welford rank-one update of the mean and of the matrix of co-moments, which
+/// stays accurate where raw sums of products would cancel. A vector can be removed again by
+/// the inverse update, so a caller that keeps the last n vectors can follow a rolling window,
+/// and accumulators built on separate shards can be merged.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MultiStats {
+    dim: usize,
+    length: usize,
+    mean: Vec<f64>,
+    // Row-major dim x dim matrix of sums of products of deviations from the mean
+    comoment: Vec<f64>,
+}
+
+impl MultiStats {
+    pub fn new(dim: usize) -> Result<Self, Error> {
+        if dim == 0 {
+            return Err(Error::InvalidParameter("dimension must be positive"));
+        }
+        let cells = dim.checked_mul(dim).ok_or(Error::InvalidParameter("dimension is too large"))?;
+        Ok(MultiStats {
+            dim,
+            length: 0,
+            mean: vec![0.0; dim],
+            comoment: vec![0.0; cells],
+        })
+    }
+
+    // Adds sign * (a - mean_a)(b - mean_b)' to the co-moments.
+    fn rank_one(&mut self, a: &[f64], b: &[f64], sign: f64) {
+        for (row, a) in self.comoment.chunks_mut(self.dim).zip(a.iter()) {
+            row.iter_mut().zip(b.iter()).for_each(|(c, b)| *c += sign * a * b);
+        }
+    }
+
+    pub fn push(&mut self, x: &[f64]) {
+        assert_eq!(x.len(), self.dim, "vector has the wrong dimension");
+        self.length += 1;
+        let n = self.length as f64;
+        let before: Vec<f64> = x.iter().zip(self.mean.iter()).map(|(x, m)| x - m).collect();
+        self.mean.iter_mut().zip(before.iter()).for_each(|(m, d)| *m += d / n);
+        let after: Vec<f64> = x.iter().zip(self.mean.iter()).map(|(x, m)| x - m).collect();
+        self.rank_one(&before, &after, 1.0);
+    }
+
+    pub fn push_vec(&mut self, v: Vec<Vec<f64>>) {
+        v.iter().for_each(|x| self.push(x));
+    }
+
+    /// Removes a vector that was pushed earlier. Removing a vector that was never pushed
+    /// leaves the statistics meaningless.
+    pub fn remove(&mut self, x: &[f64]) {
+        assert_eq!(x.len(), self.dim, "vector has the wrong dimension");
+        if self.length <= 1 {
+            self.reset();
+            return;
+        }
+        let after: Vec<f64> = x.iter().zip(self.mean.iter()).map(|(x, m)| x - m).collect();
+        self.length -= 1;
+        let n = self.length as f64;
+        self.mean.iter_mut().zip(after.iter()).for_each(|(m, d)| *m -= d / n);
+        let before: Vec<f64> = x.iter().zip(self.mean.iter()).map(|(x, m)| x - m).collect();
+        self.rank_one(&before, &after, -1.0);
+    }
+
+    /// Combines the statistics of another accumulator of the same dimension into this one.
+    pub fn merge(&mut self, other: &MultiStats) -> Result<(), Error> {
+        if self.dim != other.dim {
+            return Err(Error::InvalidParameter("cannot merge statistics of different dimensions"));
+        }
+        if other.length == 0 {
+            return Ok(());
+        }
+        let (na, nb) = (self.length as f64, other.length as f64);
+        let n = na + nb;
+        let delta: Vec<f64> = other.mean.iter().zip(self.mean.iter()).map(|(b, a)| b - a).collect();
+        self.comoment.iter_mut().zip(other.comoment.iter()).for_each(|(a, b)| *a += b);
+        let scaled: Vec<f64> = delta.iter().map(|d| d * na * nb / n).collect();
+        self.rank_one(&delta, &scaled, 1.0);
+        self.mean.iter_mut().zip(delta.iter()).for_each(|(m, d)| *m += d * nb / n);
+        self.length += other.length;
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        self.length = 0;
+        self.mean.iter_mut().for_each(|m| *m = 0.0);
+        self.comoment.iter_mut().for_each(|c| *c = 0.0);
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn mean(&self) -> Option<&[f64]> {
+        if self.length > 0 {
+            Some(&self.mean)
+        } else {
+            None
+        }
+    }
+
+    fn scaled_comoments(&self, divisor: f64) -> Vec<Vec<f64>> {
+        self.comoment.chunks(self.dim)
+            .map(|row| row.iter().map(|c| c / divisor).collect())
+            .collect()
+    }
+
+    /// Population variance of each dimension.
+    pub fn variance(&self) -> Option<Vec<f64>> {
+        if self.length == 0 {
+            return None;
+        }
+        let n = self.length as f64;
+        Some((0..self.dim).map(|i| f64::max(0.0, self.comoment[i * self.dim + i]) / n).collect())
+    }
+
+    pub fn stddev(&self) -> Option<Vec<f64>> {
+        self.variance().map(|v| v.iter().map(|v| v.sqrt()).collect())
+    }
+
+    /// Population covariance matrix, dividing by `n`.
+    pub fn covariance(&self) -> Option<Vec<Vec<f64>>> {
+        if self.length == 0 {
+            None
+        } else {
+            Some(self.scaled_comoments(self.length as f64))
+        }
+    }
+
+    /// Sample covariance matrix, dividing by `n - 1`.
+    pub fn sample_covariance(&self) -> Option<Vec<Vec<f64>>> {
+        if self.length < 2 {
+            None
+        } else {
+            Some(self.scaled_comoments((self.length - 1) as f64))
+        }
+    }
+
+    /// Pearson correlation matrix. Entries involving a constant dimension are NaN.
+    pub fn correlation(&self) -> Option<Vec<Vec<f64>>> {
+        if self.length == 0 {
+            return None;
+        }
+        let scale: Vec<f64> = (0..self.dim)
+            .map(|i| f64::sqrt(f64::max(0.0, self.comoment[i * self.dim + i])))
+            .collect();
+        Some((0..self.dim).map(|i| (0..self.dim).map(|j| {
+            if scale[i] > 0.0 && scale[j] > 0.0 {
+                (self.comoment[i * self.dim + j] / (scale[i] * scale[j])).clamp(-1.0, 1.0)
+            } else {
+                f64::NAN
+            }
+        }).collect()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use float_cmp::approx_eq;
+    use super::MultiStats;
+    use crate::error::Error;
+    use crate::test_util::values;
+    use crate::stats::{correlation, covariance, mean, stddev};
+
+    // Correlated three-dimensional vectors with a large offset on the first axis.
+    fn vectors(n: usize, seed: u64) -> Vec<Vec<f64>> {
+        let (a, b) = (values(n, seed), values(n, seed + 1));
+        a.iter().zip(b.iter()).map(|(a, b)| vec![1e6 + a, a - 2.0 * b, 5.0 * b]).collect()
+    }
+
+    fn check(ms: &MultiStats, vs: &[Vec<f64>]) {
+        let columns: Vec<Vec<f64>> = (0..3).map(|i| vs.iter().map(|v| v[i]).collect()).collect();
+        let means = ms.mean().unwrap();
+        let stddevs = ms.stddev().unwrap();
+        let cov = ms.covariance().unwrap();
+        let corr = ms.correlation().unwrap();
+        assert_eq!(ms.len(), vs.len());
+        for i in 0..3 {
+            assert!( approx_eq!(f64, means[i], mean(&columns[i]).unwrap(), epsilon = 1e-9) );
+            assert!( approx_eq!(f64, stddevs[i], stddev(&columns[i]).unwrap(), epsilon = 1e-9) );
+            for j in 0..3 {
+                assert!( approx_eq!(f64, cov[i][j], covariance(&columns[i], &columns[j]).unwrap(), epsilon = 1e-9) );
+                assert!( approx_eq!(f64, corr[i][j], correlation(&columns[i], &columns[j]).unwrap(), epsilon = 1e-9) );
+            }
+        }
+    }
+
+    #[test]
+    fn push_test() {
+        let vs = vectors(200, 1);
+        let mut ms = MultiStats::new(3).unwrap();
+        assert_eq!(ms.mean(), None);
+        ms.push_vec(vs.clone());
+        check(&ms, &vs);
+
+        let cov = ms.covariance().unwrap();
+        let sample = ms.sample_covariance().unwrap();
+        assert!( approx_eq!(f64, sample[1][2], cov[1][2] * 200.0 / 199.0, epsilon = 1e-12) );
+        assert!( approx_eq!(f64, cov[0][1], cov[1][0], epsilon = 1e-9) );
+    }
+
+    #[test]
+    fn merge_test() {
+        let vs = vectors(300, 3);
+        let mut a = MultiStats::new(3).unwrap();
+        let mut b = MultiStats::new(3).unwrap();
+        a.push_vec(vs[..70].to_vec());
+        b.push_vec(vs[70..].to_vec());
+        a.merge(&b).unwrap();
+        a.merge(&MultiStats::new(3).unwrap()).unwrap();
+        check(&a, &vs);
+
+        let mut empty = MultiStats::new(3).unwrap();
+        empty.merge(&b).unwrap();
+        check(&empty, &vs[70..]);
+        assert!(a.merge(&MultiStats::new(2).unwrap()).is_err());
+        assert_eq!(MultiStats::new(0).err(), Some(Error::InvalidParameter("dimension must be positive")));
+    }
+
+    #[test]
+    fn rolling_test() {
+        let window = 25;
+        let vs = vectors(150, 5);
+        let mut ms = MultiStats::new(3).unwrap();
+        let mut queue = VecDeque::new();
+        for (i, v) in vs.iter().enumerate() {
+            ms.push(v);
+            queue.push_back(v.clone());
+            if queue.len() > window {
+                ms.remove(&queue.pop_front().unwrap());
+            }
+            if i >= 1 {
+                check(&ms, &vs[(i + 1).saturating_sub(window)..=i]);
+            }
+        }
+        queue.iter().for_each(|v| ms.remove(v));
+        assert!(ms.is_empty());
+    }
+
+    #[test]
+    fn constant_dimension_test() {
+        let mut ms = MultiStats::new(2).unwrap();
+        ms.push_vec(vec![vec![1.0, 4.0], vec![2.0, 4.0]]);
+        let corr = ms.correlation().unwrap();
+        assert!( approx_eq!(f64, corr[0][0], 1.0, epsilon = 1e-15) );
+        assert!(corr[0][1].is_nan());
+        assert_eq!(ms.variance(), Some(vec![0.25, 0.0]));
+    }
+}
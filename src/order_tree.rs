@@ -0,0 +1,287 @@
+use std::cmp::Ordering;
+
+use num_traits::Float;
+
+use crate::rng::Rng;
+
+const NIL: usize = usize::MAX;
+const SEED: u64 = 0x7265_6170;
+
+// IEEE 754 total order, which num-traits only offers in later releases: negative NaN first,
+// then -inf up to -0 and +0, then +inf and positive NaN last.
+pub(crate) fn total_cmp<T: Float>(a: &T, b: &T) -> Ordering {
+    let class = |x: &T| match (x.is_nan(), x.is_sign_negative()) {
+        (true, true) => 0,
+        (false, _) => 1,
+        (true, false) => 2,
+    };
+    class(a).cmp(&class(b))
+        .then_with(|| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+        .then_with(|| b.is_sign_negative().cmp(&a.is_sign_negative()))
+}
+
+// The total order with -0 and +0 folded together, so that NaN finds its own node and zeros
+// fall on the same side of a threshold as `contains` puts them.
+fn value_cmp<T: Float>(a: T, b: T) -> Ordering {
+    total_cmp(&(a + T::zero()), &(b + T::zero()))
+}
+
+#[derive(Clone, Debug)]
+struct Node<T> {
+    value: T,
+    priority: u64,
+    left: usize,
+    right: usize,
+    size: usize,
+    sum: T,
+    sum_of_squares: T,
+}
+
+// Treap ordered by value where each node also caches the size, sum and sum of squares of its
+// subtree, so that the count and sum of all values below a threshold, the k-th smallest value
+// and the sums over the k smallest values are all found in O(log n).
+#[derive(Clone, Debug)]
+pub(crate) struct OrderTree<T> {
+    nodes: Vec<Node<T>>,
+    free: Vec<usize>,
+    root: usize,
+    rng: Rng,
+}
+
+impl<T: Float> Default for OrderTree<T> {
+    fn default() -> Self {
+        OrderTree {
+            nodes: vec![],
+            free: vec![],
+            root: NIL,
+            rng: Rng::new(SEED),
+        }
+    }
+}
+
+impl<T: Float> OrderTree<T> {
+    pub(crate) fn from_values(values: &[T]) -> Self {
+        let mut tree = OrderTree::default();
+        values.iter().for_each(|x| tree.insert(*x));
+        tree
+    }
+
+    fn size(&self, t: usize) -> usize {
+        if t == NIL { 0 } else { self.nodes[t].size }
+    }
+
+    fn sum(&self, t: usize) -> T {
+        if t == NIL { T::zero() } else { self.nodes[t].sum }
+    }
+
+    fn sum_of_squares(&self, t: usize) -> T {
+        if t == NIL { T::zero() } else { self.nodes[t].sum_of_squares }
+    }
+
+    // Whether value belongs left of a split at x.
+    fn below(value: T, x: T, inclusive: bool) -> bool {
+        match value_cmp(value, x) {
+            Ordering::Less => true,
+            Ordering::Equal => inclusive,
+            Ordering::Greater => false,
+        }
+    }
+
+    fn update(&mut self, t: usize) {
+        let (left, right) = (self.nodes[t].left, self.nodes[t].right);
+        let value = self.nodes[t].value;
+        self.nodes[t].size = self.size(left) + self.size(right) + 1;
+        self.nodes[t].sum = self.sum(left) + value + self.sum(right);
+        self.nodes[t].sum_of_squares = self.sum_of_squares(left) + value * value + self.sum_of_squares(right);
+    }
+
+    // Splits t into the values below x (or not above x when inclusive) and the rest.
+    fn split(&mut self, t: usize, x: T, inclusive: bool) -> (usize, usize) {
+        if t == NIL {
+            return (NIL, NIL);
+        }
+        if Self::below(self.nodes[t].value, x, inclusive) {
+            let (l, r) = self.split(self.nodes[t].right, x, inclusive);
+            self.nodes[t].right = l;
+            self.update(t);
+            (t, r)
+        } else {
+            let (l, r) = self.split(self.nodes[t].left, x, inclusive);
+            self.nodes[t].left = r;
+            self.update(t);
+            (l, t)
+        }
+    }
+
+    fn merge(&mut self, a: usize, b: usize) -> usize {
+        if a == NIL {
+            return b;
+        }
+        if b == NIL {
+            return a;
+        }
+        if self.nodes[a].priority > self.nodes[b].priority {
+            let right = self.merge(self.nodes[a].right, b);
+            self.nodes[a].right = right;
+            self.update(a);
+            a
+        } else {
+            let left = self.merge(a, self.nodes[b].left);
+            self.nodes[b].left = left;
+            self.update(b);
+            b
+        }
+    }
+
+    pub(crate) fn insert(&mut self, x: T) {
+        let node = Node {
+            value: x,
+            priority: self.rng.next_u64(),
+            left: NIL,
+            right: NIL,
+            size: 1,
+            sum: x,
+            sum_of_squares: x * x,
+        };
+        let t = match self.free.pop() {
+            Some(t) => {
+                self.nodes[t] = node;
+                t
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        let (l, r) = self.split(self.root, x, true);
+        let l = self.merge(l, t);
+        self.root = self.merge(l, r);
+    }
+
+    // Removes one occurrence of x, returning false if it is not present.
+    pub(crate) fn remove(&mut self, x: T) -> bool {
+        let (l, r) = self.split(self.root, x, false);
+        let (m, r) = self.split(r, x, true);
+        let found = m != NIL;
+        let m = if found {
+            self.free.push(m);
+            self.merge(self.nodes[m].left, self.nodes[m].right)
+        } else {
+            m
+        };
+        let r = self.merge(m, r);
+        self.root = self.merge(l, r);
+        found
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.nodes.clear();
+        self.free.clear();
+        self.root = NIL;
+    }
+
+    // Count and sum of the values below x, or not above x when inclusive.
+    pub(crate) fn rank(&self, x: T, inclusive: bool) -> (usize, T) {
+        let mut count = 0;
+        let mut sum = T::zero();
+        let mut t = self.root;
+        while t != NIL {
+            let node = &self.nodes[t];
+            if Self::below(node.value, x, inclusive) {
+                count += self.size(node.left) + 1;
+                sum = sum + self.sum(node.left) + node.value;
+                t = node.right;
+            } else {
+                t = node.left;
+            }
+        }
+        (count, sum)
+    }
+
+    // The k-th smallest value, counting from zero.
+    pub(crate) fn select(&self, mut k: usize) -> Option<T> {
+        let mut t = self.root;
+        while t != NIL {
+            let node = &self.nodes[t];
+            let left = self.size(node.left);
+            if k < left {
+                t = node.left;
+            } else if k == left {
+                return Some(node.value);
+            } else {
+                k -= left + 1;
+                t = node.right;
+            }
+        }
+        None
+    }
+
+    // Sum and sum of squares of the k smallest values.
+    pub(crate) fn prefix(&self, mut k: usize) -> (T, T) {
+        let mut sum = T::zero();
+        let mut sum_of_squares = T::zero();
+        let mut t = self.root;
+        while t != NIL && k > 0 {
+            let node = &self.nodes[t];
+            let left = self.size(node.left);
+            if k <= left {
+                t = node.left;
+            } else {
+                sum = sum + self.sum(node.left) + node.value;
+                sum_of_squares = sum_of_squares + self.sum_of_squares(node.left) + node.value * node.value;
+                k -= left + 1;
+                t = node.right;
+            }
+        }
+        (sum, sum_of_squares)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OrderTree;
+
+    #[test]
+    fn insert_remove_test() {
+        let mut tree = OrderTree::default();
+        let vs = [5.0, 1.0, 3.0, 3.0, 9.0, -2.0, 3.0];
+        vs.iter().for_each(|x| tree.insert(*x));
+
+        assert_eq!(tree.rank(f64::INFINITY, true).0, vs.len());
+        assert_eq!(tree.rank(3.0, false), (2, -1.0));
+        assert_eq!(tree.rank(3.0, true), (5, 8.0));
+        assert_eq!(tree.rank(100.0, false), (7, 22.0));
+        assert_eq!(tree.select(0), Some(-2.0));
+        assert_eq!(tree.select(3), Some(3.0));
+        assert_eq!(tree.select(6), Some(9.0));
+        assert_eq!(tree.select(7), None);
+        assert_eq!(tree.prefix(2), (-1.0, 5.0));
+        assert_eq!(tree.prefix(10), (22.0, 138.0));
+
+        assert!(tree.remove(3.0));
+        assert!(!tree.remove(4.0));
+        assert_eq!(tree.rank(3.0, true), (4, 5.0));
+
+        vs.iter().filter(|x| **x != 3.0).for_each(|x| assert!(tree.remove(*x)));
+        assert_eq!(tree.rank(f64::INFINITY, true), (2, 6.0));
+        tree.clear();
+        assert_eq!(tree.rank(f64::INFINITY, true), (0, 0.0));
+    }
+
+    #[test]
+    fn nan_zero_test() {
+        let mut tree = OrderTree::from_values(&[1.0, f64::NAN, -0.0, 2.0, f64::NAN]);
+
+        assert_eq!(tree.rank(0.0, false).0, 0);
+        assert_eq!(tree.rank(0.0, true).0, 1);
+        assert_eq!(tree.rank(f64::INFINITY, true), (3, 3.0));
+        assert!(tree.select(4).unwrap().is_nan());
+
+        assert!(tree.remove(f64::NAN));
+        assert!(tree.remove(0.0));
+        assert_eq!(tree.prefix(2).0, 3.0);
+        assert!(tree.remove(f64::NAN));
+        assert!(!tree.remove(f64::NAN));
+        assert_eq!(tree.prefix(10), (3.0, 5.0));
+    }
+}
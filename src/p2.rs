@@ -0,0 +1,312 @@
+use std::f64;
+
+use crate::accumulator::Accumulator;
+use crate::error::Error;
+
+// Moves the markers after the observation `x` has been counted as number `count`, using the
+// piecewise-parabolic prediction of Jain and Chlamtac. `probs` holds the probability targeted
+// by each marker, from 0 for the minimum to 1 for the maximum.
+fn update(heights: &mut [f64], positions: &mut [f64], probs: &[f64], count: usize, x: f64) {
+    let n = heights.len();
+    let k = if x < heights[0] {
+        heights[0] = x;
+        0
+    } else if x >= heights[n - 1] {
+        heights[n - 1] = x;
+        n - 2
+    } else {
+        (0..n - 1).find(|&i| heights[i] <= x && x < heights[i + 1]).unwrap()
+    };
+    positions.iter_mut().skip(k + 1).for_each(|p| *p += 1.0);
+
+    for i in 1..n - 1 {
+        let desired = 1.0 + (count - 1) as f64 * probs[i];
+        let d = desired - positions[i];
+        if (d >= 1.0 && positions[i + 1] - positions[i] > 1.0)
+            || (d <= -1.0 && positions[i - 1] - positions[i] < -1.0) {
+            let d = d.signum();
+            let (h0, h1, h2) = (heights[i - 1], heights[i], heights[i + 1]);
+            let (n0, n1, n2) = (positions[i - 1], positions[i], positions[i + 1]);
+            let parabolic = h1 + d / (n2 - n0)
+                * ((n1 - n0 + d) * (h2 - h1) / (n2 - n1) + (n2 - n1 - d) * (h1 - h0) / (n1 - n0));
+            heights[i] = if h0 < parabolic && parabolic < h2 {
+                parabolic
+            } else if d > 0.0 {
+                h1 + (h2 - h1) / (n2 - n1)
+            } else {
+                h1 - (h0 - h1) / (n0 - n1)
+            };
+            positions[i] += d;
+        }
+    }
+}
+
+// Estimate for probability p, interpolating between the markers once they are in place and
+// between the observations, which are kept sorted, before that.
+fn estimate(heights: &[f64], probs: &[f64], count: usize, p: f64) -> Option<f64> {
+    if count == 0 || !(0.0..=1.0).contains(&p) {
+        return None;
+    }
+    let n = heights.len();
+    if count < n {
+        let index = p * (count - 1) as f64;
+        let lower = index.floor() as usize;
+        let upper = index.ceil() as usize;
+        return Some(heights[lower] + (heights[upper] - heights[lower]) * (index - lower as f64));
+    }
+    let i = (1..n).find(|&i| p <= probs[i]).unwrap_or(n - 1);
+    let t = (p - probs[i - 1]) / (probs[i] - probs[i - 1]);
+    Some(heights[i - 1] + (heights[i] - heights[i - 1]) * t)
+}
+
+// Inserts one of the first observations into its sorted place among the `count` before it.
+fn initialize(heights: &mut [f64], count: usize, x: f64) {
+    let i = heights[..count].partition_point(|h| *h <= x);
+    heights.copy_within(i..count, i + 1);
+    heights[i] = x;
+}
+
+fn check_probability(p: f64) -> Result<(), Error> {
+    if p > 0.0 && p < 1.0 {
+        Ok(())
+    } else {
+        Err(Error::InvalidParameter("probabilities must be in (0, 1)"))
+    }
+}
+
+/// P² estimator of a single quantile using five markers and no allocation.
+///
+/// NaN values are ignored.
+#[derive(Clone, Debug)]
+pub struct P2Quantile {
+    p: f64,
+    length: usize,
+    heights: [f64; 5],
+    positions: [f64; 5],
+    probs: [f64; 5],
+}
+
+impl P2Quantile {
+    /// Creates an estimator of the quantile at probability `p`, which must be in (0, 1).
+    pub fn new(p: f64) -> Result<Self, Error> {
+        check_probability(p)?;
+        Ok(P2Quantile {
+            p,
+            length: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            probs: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        })
+    }
+
+    pub fn push(&mut self, x: f64) {
+        if x.is_nan() {
+            return;
+        }
+        if self.length < 5 {
+            initialize(&mut self.heights, self.length, x);
+        } else {
+            update(&mut self.heights, &mut self.positions, &self.probs, self.length + 1, x);
+        }
+        self.length += 1;
+    }
+
+    pub fn reset(&mut self) {
+        self.length = 0;
+        self.heights = [0.0; 5];
+        self.positions = [1.0, 2.0, 3.0, 4.0, 5.0];
+    }
+
+    pub fn p(&self) -> f64 {
+        self.p
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn quantile(&self) -> Option<f64> {
+        estimate(&self.heights, &self.probs, self.length, self.p)
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        estimate(&self.heights, &self.probs, self.length, 0.0)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        estimate(&self.heights, &self.probs, self.length, 1.0)
+    }
+}
+
+impl Accumulator for P2Quantile {
+    fn push(&mut self, x: f64) {
+        P2Quantile::push(self, x);
+    }
+}
+
+/// Extended P² estimator tracking several quantiles with `2m + 3` markers.
+///
+/// Markers sit at each requested probability, halfway between neighbouring ones and at the
+/// extremes. Memory is allocated once on construction. NaN values are ignored.
+#[derive(Clone, Debug)]
+pub struct P2Quantiles {
+    length: usize,
+    heights: Vec<f64>,
+    positions: Vec<f64>,
+    probs: Vec<f64>,
+}
+
+impl P2Quantiles {
+    /// Creates an estimator for the probabilities in `ps`, which must all be in (0, 1).
+    /// Duplicates are tracked once.
+    pub fn new(ps: &[f64]) -> Result<Self, Error> {
+        ps.iter().try_for_each(|p| check_probability(*p))?;
+        let mut sorted = ps.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        sorted.dedup();
+        let mut probs = vec![0.0];
+        let mut previous = 0.0;
+        for p in sorted {
+            probs.push((previous + p) / 2.0);
+            probs.push(p);
+            previous = p;
+        }
+        probs.push((previous + 1.0) / 2.0);
+        probs.push(1.0);
+
+        let n = probs.len();
+        Ok(P2Quantiles {
+            length: 0,
+            heights: vec![0.0; n],
+            positions: (1..=n).map(|i| i as f64).collect(),
+            probs,
+        })
+    }
+
+    pub fn push(&mut self, x: f64) {
+        if x.is_nan() {
+            return;
+        }
+        if self.length < self.heights.len() {
+            initialize(&mut self.heights, self.length, x);
+        } else {
+            update(&mut self.heights, &mut self.positions, &self.probs, self.length + 1, x);
+        }
+        self.length += 1;
+    }
+
+    pub fn reset(&mut self) {
+        let n = self.heights.len();
+        self.length = 0;
+        self.heights = vec![0.0; n];
+        self.positions = (1..=n).map(|i| i as f64).collect();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    /// Estimate of any quantile, interpolated between the markers on either side of `p`.
+    pub fn quantile(&self, p: f64) -> Option<f64> {
+        estimate(&self.heights, &self.probs, self.length, p)
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        self.quantile(0.0)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        self.quantile(1.0)
+    }
+}
+
+impl Accumulator for P2Quantiles {
+    fn push(&mut self, x: f64) {
+        P2Quantiles::push(self, x);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+    use super::{P2Quantile, P2Quantiles};
+    use crate::test_util::values;
+    use crate::accumulator::Accumulator;
+    use crate::fstats_f64::Stats;
+    use crate::stats::{mean, stddev, quantile, min, max};
+
+    #[test]
+    fn quantile_test() {
+        let vs = values(20000, 1);
+        for p in [0.1, 0.5, 0.9, 0.99].iter() {
+            let mut p2 = P2Quantile::new(*p).unwrap();
+            p2.push_vec(vs.clone());
+
+            assert_eq!(p2.len(), vs.len());
+            assert!( approx_eq!(f64, quantile(&vs, *p).unwrap(), p2.quantile().unwrap(), epsilon = 1e-2), "p = {}", p );
+        }
+    }
+
+    #[test]
+    fn small_test() {
+        let mut p2 = P2Quantile::new(0.5).unwrap();
+        assert_eq!(p2.quantile(), None);
+
+        let vs = vec![5.0, 1.0, 4.0];
+        p2.push_vec(vs.clone());
+        p2.push(f64::NAN);
+        assert_eq!(p2.len(), 3);
+        assert_eq!(p2.quantile(), quantile(&vs, 0.5));
+        assert_eq!(p2.min(), min(&vs));
+        assert_eq!(p2.max(), max(&vs));
+    }
+
+    #[test]
+    fn parameter_test() {
+        for p in [0.0, 1.0, -0.5, f64::NAN].iter() {
+            assert!(P2Quantile::new(*p).is_err());
+            assert!(P2Quantiles::new(&[0.5, *p]).is_err());
+        }
+
+        let mut p2 = P2Quantiles::new(&[0.25, 0.25]).unwrap();
+        p2.push_vec(values(1000, 4));
+        p2.push(f64::NAN);
+        assert_eq!(p2.len(), 1000);
+        assert!(!p2.quantile(0.25).unwrap().is_nan());
+    }
+
+    #[test]
+    fn quantiles_test() {
+        // Sum of uniforms gives a bell shaped distribution
+        let us = values(60000, 2);
+        let vs: Vec<f64> = us.chunks(3).map(|c| c.iter().sum()).collect();
+        let mut p2 = P2Quantiles::new(&[0.99, 0.5, 0.9, 0.5]).unwrap();
+        p2.push_vec(vs.clone());
+
+        for p in [0.5, 0.9, 0.99].iter() {
+            assert!( approx_eq!(f64, quantile(&vs, *p).unwrap(), p2.quantile(*p).unwrap(), epsilon = 2e-2), "p = {}", p );
+        }
+        assert!( approx_eq!(f64, quantile(&vs, 0.7).unwrap(), p2.quantile(0.7).unwrap(), epsilon = 5e-2) );
+        assert_eq!(p2.min(), min(&vs));
+        assert_eq!(p2.max(), max(&vs));
+    }
+
+    #[test]
+    fn collector_test() {
+        let vs = values(10000, 3);
+        let mut collector = (Stats::new(), P2Quantile::new(0.99).unwrap());
+        collector.push_vec(vs.clone());
+
+        assert_eq!(mean(&vs), collector.0.mean());
+        assert!( approx_eq!(f64, stddev(&vs).unwrap(), collector.0.stddev().unwrap(), epsilon = 1e-12) );
+        assert!( approx_eq!(f64, quantile(&vs, 0.99).unwrap(), collector.1.quantile().unwrap(), epsilon = 1e-2) );
+    }
+}
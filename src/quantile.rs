@@ -0,0 +1,11 @@
+use crate::accumulator::Accumulator;
+
+/// Common interface of the quantile sketches, so either can back quantile queries on a stream.
+pub trait QuantileSketch: Accumulator {
+    fn merge(&mut self, other: &Self);
+
+    fn quantile(&mut self, q: f64) -> Option<f64>;
+
+    /// Estimated fraction of the stream less than or equal to `x`.
+    fn rank(&mut self, x: f64) -> Option<f64>;
+}
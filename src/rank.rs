@@ -0,0 +1,249 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::f64;
+
+use crate::error::Error;
+use crate::stats::sign;
+
+// Change in the average rank of an existing value when `x` joins its sample, with flags for
+// whether the existing value is below or equal to `x`.
+fn rank_shift(existing: f64, x: f64) -> (f64, usize, usize) {
+    match existing.partial_cmp(&x) {
+        Some(Ordering::Greater) => (1.0, 0, 0),
+        Some(Ordering::Equal) => (0.5, 0, 1),
+        _ => (0.0, 1, 0),
+    }
+}
+
+/// Spearman's rho and Kendall's tau-b over a stream of `(x, y)` pairs.
+///
+/// The average rank of every pair is kept and shifted in place as pairs arrive and leave,
+/// together with Kendall's concordance sum S and the number of tied pairs on each axis, so
+/// nothing is ever re-sorted and each update costs O(n). With a window only the most recent
+/// pairs are kept.
+#[derive(Clone, Debug, Default)]
+pub struct RankCorrelation {
+    data: VecDeque<(f64, f64)>,
+    ranks: VecDeque<(f64, f64)>,
+    window: Option<usize>,
+    concordance: f64,
+    ties_x: usize,
+    ties_y: usize,
+    // Sums of products of ranks centered on their mean (n + 1) / 2
+    centered_xy: f64,
+    centered_xx: f64,
+    centered_yy: f64,
+}
+
+impl RankCorrelation {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_window(window: usize) -> Result<Self, Error> {
+        if window == 0 {
+            return Err(Error::InvalidParameter("window must be positive"));
+        }
+        Ok(RankCorrelation {
+            window: Some(window),
+            ..Default::default()
+        })
+    }
+
+    fn recenter(&mut self) {
+        let mean = (self.data.len() + 1) as f64 / 2.0;
+        self.centered_xy = 0.0;
+        self.centered_xx = 0.0;
+        self.centered_yy = 0.0;
+        for (rx, ry) in self.ranks.iter() {
+            self.centered_xy += (rx - mean) * (ry - mean);
+            self.centered_xx += (rx - mean) * (rx - mean);
+            self.centered_yy += (ry - mean) * (ry - mean);
+        }
+    }
+
+    pub fn push(&mut self, x: f64, y: f64) {
+        let (mut below_x, mut equal_x, mut below_y, mut equal_y) = (0, 0, 0, 0);
+        for ((xi, yi), (rx, ry)) in self.data.iter().zip(self.ranks.iter_mut()) {
+            let (shift, below, equal) = rank_shift(*xi, x);
+            *rx += shift;
+            below_x += below;
+            equal_x += equal;
+            let (shift, below, equal) = rank_shift(*yi, y);
+            *ry += shift;
+            below_y += below;
+            equal_y += equal;
+            self.concordance += sign(x - xi) * sign(y - yi);
+        }
+        self.data.push_back((x, y));
+        self.ranks.push_back((
+            below_x as f64 + 1.0 + equal_x as f64 / 2.0,
+            below_y as f64 + 1.0 + equal_y as f64 / 2.0,
+        ));
+        self.ties_x += equal_x;
+        self.ties_y += equal_y;
+        if self.window.is_some_and(|w| self.data.len() > w) {
+            self.remove(0);
+        } else {
+            self.recenter();
+        }
+    }
+
+    pub fn push_vec(&mut self, v: Vec<(f64, f64)>) {
+        v.iter().for_each(|(x, y)| self.push(*x, *y));
+    }
+
+    pub fn remove(&mut self, index: usize) -> (f64, f64) {
+        let (x, y) = self.data.remove(index).expect("index out of bounds");
+        self.ranks.remove(index);
+        let (mut equal_x, mut equal_y) = (0, 0);
+        for ((xi, yi), (rx, ry)) in self.data.iter().zip(self.ranks.iter_mut()) {
+            let (shift, _, equal) = rank_shift(*xi, x);
+            *rx -= shift;
+            equal_x += equal;
+            let (shift, _, equal) = rank_shift(*yi, y);
+            *ry -= shift;
+            equal_y += equal;
+            self.concordance -= sign(x - xi) * sign(y - yi);
+        }
+        self.ties_x -= equal_x;
+        self.ties_y -= equal_y;
+        self.recenter();
+        (x, y)
+    }
+
+    /// Removes the `n` oldest pairs, or all of them if there are fewer.
+    pub fn trim(&mut self, n: usize) {
+        for _ in 0..usize::min(n, self.data.len()) {
+            self.remove(0);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = RankCorrelation {
+            window: self.window,
+            ..Default::default()
+        };
+    }
+
+    pub fn window(&self) -> Option<usize> {
+        self.window
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Spearman's rank correlation, the Pearson correlation of the average ranks, or `None`
+    /// when either axis is constant.
+    pub fn spearman(&self) -> Option<f64> {
+        if self.len() < 2 || self.centered_xx <= 0.0 || self.centered_yy <= 0.0 {
+            None
+        } else {
+            Some((self.centered_xy / f64::sqrt(self.centered_xx * self.centered_yy)).clamp(-1.0, 1.0))
+        }
+    }
+
+    /// Kendall's tau-b, which corrects for ties on either axis, or `None` when either axis is
+    /// constant.
+    pub fn kendall(&self) -> Option<f64> {
+        let n = self.len();
+        if n < 2 {
+            return None;
+        }
+        let pairs = n * (n - 1) / 2;
+        let denominator = f64::sqrt((pairs - self.ties_x) as f64 * (pairs - self.ties_y) as f64);
+        if denominator > 0.0 {
+            Some(self.concordance / denominator)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+    use super::RankCorrelation;
+    use crate::error::Error;
+    use crate::test_util::values;
+    use crate::stats::{kendall, spearman};
+
+    // Pairs rounded to a coarse grid so that both axes have plenty of ties.
+    fn pairs(n: usize, seed: u64) -> (Vec<f64>, Vec<f64>) {
+        let xs: Vec<f64> = values(n, seed).iter().map(|x| (x * 8.0).floor()).collect();
+        let ys: Vec<f64> = xs.iter().zip(values(n, seed + 1).iter())
+            .map(|(x, e)| (x + e * 6.0).floor())
+            .collect();
+        (xs, ys)
+    }
+
+    fn check(rc: &RankCorrelation, xs: &[f64], ys: &[f64]) {
+        assert_eq!(rc.len(), xs.len());
+        assert!( approx_eq!(f64, rc.spearman().unwrap(), spearman(xs, ys).unwrap(), epsilon = 1e-12) );
+        assert!( approx_eq!(f64, rc.kendall().unwrap(), kendall(xs, ys).unwrap(), epsilon = 1e-12) );
+    }
+
+    #[test]
+    fn batch_test() {
+        let xs = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let ys = [1.0, 8.0, 27.0, 64.0, 1000.0];
+        assert!( approx_eq!(f64, spearman(&xs, &ys).unwrap(), 1.0, epsilon = 1e-15) );
+        assert!( approx_eq!(f64, kendall(&xs, &ys).unwrap(), 1.0, epsilon = 1e-15) );
+
+        let ys = [2.0, 1.0, 4.0, 3.0, 5.0];
+        assert!( approx_eq!(f64, spearman(&xs, &ys).unwrap(), 0.8, epsilon = 1e-15) );
+        assert!( approx_eq!(f64, kendall(&xs, &ys).unwrap(), 0.6, epsilon = 1e-15) );
+
+        // S = 2 with one tied pair on each axis, so tau-b = 2 / sqrt(5 * 5)
+        assert!( approx_eq!(f64, kendall(&[1.0, 2.0, 2.0, 3.0], &[1.0, 3.0, 2.0, 2.0]).unwrap(), 0.4, epsilon = 1e-15) );
+        assert_eq!(kendall(&[1.0, 1.0], &[1.0, 2.0]), None);
+    }
+
+    #[test]
+    fn push_remove_test() {
+        let (xs, ys) = pairs(120, 1);
+        let mut rc = RankCorrelation::new();
+        assert_eq!(rc.spearman(), None);
+        rc.push_vec(xs.iter().copied().zip(ys.iter().copied()).collect());
+        check(&rc, &xs, &ys);
+
+        assert_eq!(rc.remove(30), (xs[30], ys[30]));
+        rc.trim(10);
+        check(&rc, &[&xs[10..30], &xs[31..]].concat(), &[&ys[10..30], &ys[31..]].concat());
+
+        rc.reset();
+        assert!(rc.is_empty());
+        assert_eq!(rc.kendall(), None);
+    }
+
+    #[test]
+    fn window_test() {
+        let window = 30;
+        let (xs, ys) = pairs(200, 3);
+        let mut rc = RankCorrelation::with_window(window).unwrap();
+        for i in 0..xs.len() {
+            rc.push(xs[i], ys[i]);
+            if i >= 5 {
+                let start = (i + 1).saturating_sub(window);
+                check(&rc, &xs[start..=i], &ys[start..=i]);
+            }
+        }
+        assert_eq!(rc.window(), Some(window));
+        assert_eq!(RankCorrelation::with_window(0).err(), Some(Error::InvalidParameter("window must be positive")));
+    }
+
+    #[test]
+    fn outlier_test() {
+        let mut rc = RankCorrelation::new();
+        (0..20).for_each(|i| rc.push(i as f64, i as f64));
+        rc.push(20.0, -1e9);
+
+        assert!(rc.spearman().unwrap() > 0.7);
+        assert!(rc.kendall().unwrap() > 0.8);
+    }
+}
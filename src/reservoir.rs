@@ -0,0 +1,406 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::f64;
+
+use crate::accumulator::Accumulator;
+use crate::error::Error;
+use crate::fstats_f64::Stats;
+use crate::rng::Rng;
+
+const SEED: u64 = 0x7265_7365_7276_6f69;
+
+/// Uniform random sample of fixed size from a stream, using Algorithm R.
+///
+/// Every item seen so far has the same probability `capacity / len` of being in the sample.
+/// Each push draws one random number; `SkipReservoir` produces the same distribution while
+/// drawing far fewer.
+#[derive(Clone, Debug)]
+pub struct Reservoir<T> {
+    capacity: usize,
+    sample: Vec<T>,
+    count: u64,
+    rng: Rng,
+}
+
+impl<T> Reservoir<T> {
+    pub fn new(capacity: usize) -> Result<Self, Error> {
+        Reservoir::with_seed(capacity, SEED)
+    }
+
+    pub fn with_seed(capacity: usize, seed: u64) -> Result<Self, Error> {
+        if capacity == 0 {
+            return Err(Error::InvalidParameter("capacity must be positive"));
+        }
+        Ok(Reservoir {
+            capacity,
+            sample: Vec::with_capacity(capacity),
+            count: 0,
+            rng: Rng::new(seed),
+        })
+    }
+
+    pub fn push(&mut self, x: T) {
+        self.count += 1;
+        if self.sample.len() < self.capacity {
+            self.sample.push(x);
+        } else {
+            let j = self.rng.next_below(self.count) as usize;
+            if j < self.capacity {
+                self.sample[j] = x;
+            }
+        }
+    }
+
+    pub fn push_vec(&mut self, v: Vec<T>) {
+        v.into_iter().for_each(|x| self.push(x));
+    }
+
+    pub fn reset(&mut self) {
+        self.sample.clear();
+        self.count = 0;
+    }
+
+    pub fn sample(&self) -> &[T] {
+        &self.sample
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of items seen, which may exceed the size of the sample.
+    pub fn len(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+impl Reservoir<f64> {
+    /// `Stats` over the sampled values.
+    pub fn stats(&self) -> Stats {
+        Stats::from(self.sample())
+    }
+}
+
+impl Accumulator for Reservoir<f64> {
+    fn push(&mut self, x: f64) {
+        Reservoir::push(self, x);
+    }
+}
+
+/// Uniform random sample of fixed size from a stream, using Li's Algorithm L.
+///
+/// Instead of drawing a random number for every item it computes how many items to skip
+/// before the next replacement, so the cost grows with `capacity * ln(len / capacity)` rather
+/// than with the length of the stream.
+#[derive(Clone, Debug)]
+pub struct SkipReservoir<T> {
+    capacity: usize,
+    sample: Vec<T>,
+    count: u64,
+    w: f64,
+    next: u64,
+    rng: Rng,
+}
+
+impl<T> SkipReservoir<T> {
+    pub fn new(capacity: usize) -> Result<Self, Error> {
+        SkipReservoir::with_seed(capacity, SEED)
+    }
+
+    pub fn with_seed(capacity: usize, seed: u64) -> Result<Self, Error> {
+        if capacity == 0 {
+            return Err(Error::InvalidParameter("capacity must be positive"));
+        }
+        Ok(SkipReservoir {
+            capacity,
+            sample: Vec::with_capacity(capacity),
+            count: 0,
+            w: 1.0,
+            next: 0,
+            rng: Rng::new(seed),
+        })
+    }
+
+    // Shrinks the threshold w and moves `next` past the items that will not be selected.
+    fn advance(&mut self) {
+        let k = self.capacity as f64;
+        self.w *= f64::exp(f64::ln(self.rng.next_f64()) / k);
+        let skip = f64::floor(f64::ln(self.rng.next_f64()) / f64::ln(1.0 - self.w));
+        self.next = self.next.saturating_add(skip as u64).saturating_add(1);
+    }
+
+    pub fn push(&mut self, x: T) {
+        if self.sample.len() < self.capacity {
+            self.sample.push(x);
+            if self.sample.len() == self.capacity {
+                self.next = self.count;
+                self.advance();
+            }
+        } else if self.count == self.next {
+            let j = self.rng.next_below(self.capacity as u64) as usize;
+            self.sample[j] = x;
+            self.advance();
+        }
+        self.count += 1;
+    }
+
+    pub fn push_vec(&mut self, v: Vec<T>) {
+        v.into_iter().for_each(|x| self.push(x));
+    }
+
+    pub fn reset(&mut self) {
+        self.sample.clear();
+        self.count = 0;
+        self.w = 1.0;
+        self.next = 0;
+    }
+
+    pub fn sample(&self) -> &[T] {
+        &self.sample
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of items seen, which may exceed the size of the sample.
+    pub fn len(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+impl SkipReservoir<f64> {
+    /// `Stats` over the sampled values.
+    pub fn stats(&self) -> Stats {
+        Stats::from(self.sample())
+    }
+}
+
+impl Accumulator for SkipReservoir<f64> {
+    fn push(&mut self, x: f64) {
+        SkipReservoir::push(self, x);
+    }
+}
+
+// Sampled item with the logarithm of its A-Res key, ordered so that the smallest key is on
+// top of the heap.
+#[derive(Clone, Debug)]
+struct Keyed<T> {
+    key: f64,
+    item: T,
+}
+
+impl<T> PartialEq for Keyed<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<T> Eq for Keyed<T> {}
+
+impl<T> PartialOrd for Keyed<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Keyed<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key.total_cmp(&self.key)
+    }
+}
+
+/// Weighted random sample without replacement, using Efraimidis and Spirakis' A-ExpJ.
+///
+/// Each item gets the key `u^(1 / weight)` and the sample keeps the items with the largest
+/// keys, so heavier items are proportionally more likely to be kept. Exponential jumps skip
+/// over items that cannot enter the sample without drawing a random number for them. Keys
+/// are kept as logarithms so that very small weights do not underflow. Items with a weight
+/// that is not positive are never sampled.
+#[derive(Clone, Debug)]
+pub struct WeightedReservoir<T> {
+    capacity: usize,
+    heap: BinaryHeap<Keyed<T>>,
+    count: u64,
+    total_weight: f64,
+    jump: f64,
+    rng: Rng,
+}
+
+impl<T> WeightedReservoir<T> {
+    pub fn new(capacity: usize) -> Result<Self, Error> {
+        WeightedReservoir::with_seed(capacity, SEED)
+    }
+
+    pub fn with_seed(capacity: usize, seed: u64) -> Result<Self, Error> {
+        if capacity == 0 {
+            return Err(Error::InvalidParameter("capacity must be positive"));
+        }
+        Ok(WeightedReservoir {
+            capacity,
+            heap: BinaryHeap::with_capacity(capacity),
+            count: 0,
+            total_weight: 0.0,
+            jump: 0.0,
+            rng: Rng::new(seed),
+        })
+    }
+
+    fn min_key(&self) -> f64 {
+        self.heap.peek().map_or(0.0, |k| k.key)
+    }
+
+    // Weight to skip before the next item that replaces the smallest key.
+    fn draw_jump(&mut self) {
+        self.jump = f64::ln(self.rng.next_f64()) / self.min_key();
+    }
+
+    pub fn push(&mut self, x: T, weight: f64) {
+        self.count += 1;
+        if weight.is_nan() || weight <= 0.0 {
+            return;
+        }
+        self.total_weight += weight;
+        if self.heap.len() < self.capacity {
+            let key = f64::ln(self.rng.next_f64()) / weight;
+            self.heap.push(Keyed { key, item: x });
+            if self.heap.len() == self.capacity {
+                self.draw_jump();
+            }
+            return;
+        }
+        self.jump -= weight;
+        if self.jump <= 0.0 {
+            // The new key is drawn conditioned on beating the current smallest key.
+            let t = f64::exp(weight * self.min_key());
+            let u = t + (1.0 - t) * self.rng.next_f64();
+            let key = f64::ln(u) / weight;
+            self.heap.pop();
+            self.heap.push(Keyed { key, item: x });
+            self.draw_jump();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.heap.clear();
+        self.count = 0;
+        self.total_weight = 0.0;
+        self.jump = 0.0;
+    }
+
+    /// Sampled items, in no particular order.
+    pub fn sample(&self) -> Vec<&T> {
+        self.heap.iter().map(|k| &k.item).collect()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of items seen, including those with a weight that is not positive.
+    pub fn len(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn total_weight(&self) -> f64 {
+        self.total_weight
+    }
+}
+
+impl WeightedReservoir<f64> {
+    /// `Stats` over the sampled values.
+    pub fn stats(&self) -> Stats {
+        self.heap.iter().map(|k| k.item).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+    use super::{Reservoir, SkipReservoir, WeightedReservoir};
+    use crate::error::Error;
+
+    const TRIALS: u64 = 4000;
+
+    // Fraction of trials in which each of 0..n ends up in the sample.
+    fn inclusion<F: Fn(u64) -> Vec<usize>>(n: usize, sample: F) -> Vec<f64> {
+        let mut counts = vec![0; n];
+        for seed in 0..TRIALS {
+            sample(seed).iter().for_each(|i| counts[*i] += 1);
+        }
+        counts.iter().map(|c| *c as f64 / TRIALS as f64).collect()
+    }
+
+    #[test]
+    fn algorithm_r_test() {
+        let fractions = inclusion(40, |seed| {
+            let mut r = Reservoir::with_seed(8, seed).unwrap();
+            r.push_vec((0..40).collect());
+            r.sample().to_vec()
+        });
+        fractions.iter().for_each(|f| assert!((f - 0.2).abs() < 0.03));
+
+        let mut r = Reservoir::new(3).unwrap();
+        r.push_vec(vec![1.0, 2.0]);
+        assert_eq!(r.sample(), &[1.0, 2.0]);
+        assert!( approx_eq!(f64, r.stats().mean().unwrap(), 1.5, epsilon = 1e-15) );
+    }
+
+    #[test]
+    fn algorithm_l_test() {
+        let fractions = inclusion(40, |seed| {
+            let mut r = SkipReservoir::with_seed(8, seed).unwrap();
+            r.push_vec((0..40).collect());
+            r.sample().to_vec()
+        });
+        fractions.iter().for_each(|f| assert!((f - 0.2).abs() < 0.03));
+
+        let mut r = SkipReservoir::new(100).unwrap();
+        r.push_vec((0..1_000_000).map(|i| i as f64).collect());
+        assert_eq!(r.len(), 1_000_000);
+        assert_eq!(r.sample().len(), 100);
+        assert!((r.stats().mean().unwrap() - 500_000.0).abs() < 100_000.0);
+    }
+
+    #[test]
+    fn weighted_test() {
+        // With a single slot the item is kept with probability proportional to its weight.
+        let weights = [1.0, 2.0, 3.0, 4.0, 0.0];
+        let fractions = inclusion(weights.len(), |seed| {
+            let mut r = WeightedReservoir::with_seed(1, seed).unwrap();
+            weights.iter().enumerate().for_each(|(i, w)| r.push(i, *w));
+            r.sample().into_iter().copied().collect()
+        });
+        for (f, w) in fractions.iter().zip(weights.iter()) {
+            assert!((f - w / 10.0).abs() < 0.03);
+        }
+
+        let mut r = WeightedReservoir::new(50).unwrap();
+        (0..10_000).for_each(|i| r.push(i as f64, if i < 5_000 { 1.0 } else { 9.0 }));
+        let heavy = r.sample().iter().filter(|x| ***x >= 5_000.0).count();
+        assert_eq!(r.sample().len(), 50);
+        assert!( approx_eq!(f64, r.total_weight(), 50_000.0, epsilon = 1e-9) );
+        assert!(heavy > 35);
+        assert_eq!(r.stats().len(), 50);
+    }
+
+    #[test]
+    fn capacity_test() {
+        let error = Some(Error::InvalidParameter("capacity must be positive"));
+        assert_eq!(Reservoir::<f64>::new(0).err(), error);
+        assert_eq!(SkipReservoir::<f64>::with_seed(0, 1).err(), error);
+        assert_eq!(WeightedReservoir::<f64>::new(0).err(), error);
+    }
+}
@@ -0,0 +1,208 @@
+use std::f64;
+
+use crate::error::Error;
+
+const PRIOR_VARIANCE: f64 = 1e6;
+
+/// Online multiple linear regression by recursive least squares.
+///
+/// Instead of refitting on all data it keeps the coefficients and the d x d inverse of the
+/// weighted information matrix, and updates both in O(d²) per observation. Older observations
+/// are discounted by the forgetting factor, so with a factor below one the fit tracks
+/// coefficients that drift over time; a factor of one gives ordinary least squares. There is no
+/// implicit intercept, so include a constant feature of 1.0 to fit one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecursiveLeastSquares {
+    dim: usize,
+    forgetting: f64,
+    prior_variance: f64,
+    coefficients: Vec<f64>,
+    // Row-major d x d matrix proportional to the covariance of the coefficients
+    p: Vec<f64>,
+    length: usize,
+    weight: f64,
+    residual_sum_of_squares: f64,
+}
+
+impl RecursiveLeastSquares {
+    pub fn new(dim: usize, forgetting: f64) -> Result<Self, Error> {
+        RecursiveLeastSquares::with_prior(dim, forgetting, PRIOR_VARIANCE)
+    }
+
+    /// Starts from zero coefficients with the given prior variance. Smaller values regularize
+    /// the early fit towards zero more strongly.
+    pub fn with_prior(dim: usize, forgetting: f64, prior_variance: f64) -> Result<Self, Error> {
+        if dim == 0 {
+            return Err(Error::InvalidParameter("dimension must be positive"));
+        }
+        if forgetting.is_nan() || forgetting <= 0.0 || forgetting > 1.0 {
+            return Err(Error::InvalidParameter("forgetting factor must be in (0, 1]"));
+        }
+        if prior_variance.is_nan() || prior_variance <= 0.0 {
+            return Err(Error::InvalidParameter("prior variance must be positive"));
+        }
+        let mut rls = RecursiveLeastSquares {
+            dim,
+            forgetting,
+            prior_variance,
+            coefficients: vec![],
+            p: vec![],
+            length: 0,
+            weight: 0.0,
+            residual_sum_of_squares: 0.0,
+        };
+        rls.reset();
+        Ok(rls)
+    }
+
+    pub fn push(&mut self, features: &[f64], target: f64) {
+        assert_eq!(features.len(), self.dim, "features have the wrong dimension");
+        let d = self.dim;
+        let px: Vec<f64> = self.p.chunks(d)
+            .map(|row| row.iter().zip(features.iter()).map(|(p, x)| p * x).sum())
+            .collect();
+        let denominator = self.forgetting + features.iter().zip(px.iter()).map(|(x, p)| x * p).sum::<f64>();
+        let gain: Vec<f64> = px.iter().map(|p| p / denominator).collect();
+
+        let prior_error = target - self.predict(features);
+        self.coefficients.iter_mut().zip(gain.iter()).for_each(|(c, k)| *c += k * prior_error);
+        let posterior_error = target - self.predict(features);
+
+        // P = (P - P x x' P / denominator) / forgetting, written so that P stays symmetric
+        for (row, pi) in self.p.chunks_mut(d).zip(px.iter()) {
+            row.iter_mut().zip(px.iter())
+                .for_each(|(p, pj)| *p = (*p - pi * pj / denominator) / self.forgetting);
+        }
+
+        self.length += 1;
+        self.weight = self.forgetting * self.weight + 1.0;
+        self.residual_sum_of_squares = self.forgetting * self.residual_sum_of_squares
+            + prior_error * posterior_error;
+    }
+
+    pub fn predict(&self, features: &[f64]) -> f64 {
+        assert_eq!(features.len(), self.dim, "features have the wrong dimension");
+        self.coefficients.iter().zip(features.iter()).map(|(c, x)| c * x).sum()
+    }
+
+    pub fn coefficients(&self) -> &[f64] {
+        &self.coefficients
+    }
+
+    /// Variance of the residuals, from the discounted sum of squared residuals and the
+    /// effective number of observations less the number of coefficients.
+    pub fn residual_variance(&self) -> Option<f64> {
+        let freedom = self.weight - self.dim as f64;
+        if freedom <= 0.0 {
+            None
+        } else {
+            Some(f64::max(0.0, self.residual_sum_of_squares) / freedom)
+        }
+    }
+
+    /// Estimated covariance matrix of the coefficients.
+    pub fn covariance(&self) -> Option<Vec<Vec<f64>>> {
+        let variance = self.residual_variance()?;
+        Some(self.p.chunks(self.dim)
+            .map(|row| row.iter().map(|p| p * variance).collect())
+            .collect())
+    }
+
+    pub fn reset(&mut self) {
+        let d = self.dim;
+        self.coefficients = vec![0.0; d];
+        self.p = (0..d * d).map(|i| if i % (d + 1) == 0 { self.prior_variance } else { 0.0 }).collect();
+        self.length = 0;
+        self.weight = 0.0;
+        self.residual_sum_of_squares = 0.0;
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn forgetting(&self) -> f64 {
+        self.forgetting
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+    use super::RecursiveLeastSquares;
+    use crate::test_util::values;
+    use crate::bivariate::PairedStats;
+
+    #[test]
+    fn parameter_test() {
+        assert!(RecursiveLeastSquares::new(0, 1.0).is_err());
+        assert!(RecursiveLeastSquares::new(2, 0.0).is_err());
+        assert!(RecursiveLeastSquares::new(2, 1.5).is_err());
+        assert!(RecursiveLeastSquares::with_prior(2, 1.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn exact_fit_test() {
+        let (a, b) = (values(100, 1), values(100, 2));
+        let mut rls = RecursiveLeastSquares::new(3, 1.0).unwrap();
+        for (x1, x2) in a.iter().zip(b.iter()) {
+            rls.push(&[1.0, *x1, *x2], 1.0 + 2.0 * x1 - 3.0 * x2);
+        }
+
+        let expected = [1.0, 2.0, -3.0];
+        rls.coefficients().iter().zip(expected.iter())
+            .for_each(|(c, e)| assert!( approx_eq!(f64, *c, *e, epsilon = 1e-5) ));
+        assert!( approx_eq!(f64, rls.predict(&[1.0, 0.5, 0.5]), 0.5, epsilon = 1e-5) );
+        assert_eq!(rls.len(), 100);
+
+        rls.reset();
+        assert!(rls.is_empty());
+        assert_eq!(rls.coefficients(), &[0.0, 0.0, 0.0]);
+        assert_eq!(rls.covariance(), None);
+    }
+
+    #[test]
+    fn ordinary_least_squares_test() {
+        // Without forgetting the fit matches the simple linear regression.
+        let xs = values(200, 3);
+        let ys: Vec<f64> = xs.iter().zip(values(200, 4).iter()).map(|(x, e)| 0.5 + x + e).collect();
+        let mut rls = RecursiveLeastSquares::new(2, 1.0).unwrap();
+        let mut ps = PairedStats::new();
+        for (x, y) in xs.iter().zip(ys.iter()) {
+            rls.push(&[1.0, *x], *y);
+            ps.push(*x, *y);
+        }
+
+        assert!( approx_eq!(f64, rls.coefficients()[0], ps.intercept().unwrap(), epsilon = 1e-4) );
+        assert!( approx_eq!(f64, rls.coefficients()[1], ps.slope().unwrap(), epsilon = 1e-4) );
+        let covariance = rls.covariance().unwrap();
+        assert!( approx_eq!(f64, covariance[1][1].sqrt(), ps.slope_standard_error().unwrap(), epsilon = 1e-4) );
+        assert!( approx_eq!(f64, covariance[0][0].sqrt(), ps.intercept_standard_error().unwrap(), epsilon = 1e-4) );
+        assert!( approx_eq!(f64, covariance[0][1], covariance[1][0], epsilon = 1e-15) );
+    }
+
+    #[test]
+    fn forgetting_test() {
+        // The slope changes from 2 to -1 halfway through; forgetting lets the fit follow it.
+        let xs = values(400, 5);
+        let mut tracking = RecursiveLeastSquares::new(2, 0.95).unwrap();
+        let mut fixed = RecursiveLeastSquares::new(2, 1.0).unwrap();
+        for (i, x) in xs.iter().enumerate() {
+            let y = if i < 200 { 2.0 * x } else { -x };
+            tracking.push(&[1.0, *x], y);
+            fixed.push(&[1.0, *x], y);
+        }
+
+        assert!( approx_eq!(f64, tracking.coefficients()[1], -1.0, epsilon = 1e-3) );
+        assert!(fixed.coefficients()[1] > 0.0);
+        assert_eq!(tracking.forgetting(), 0.95);
+    }
+}
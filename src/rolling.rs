@@ -0,0 +1,106 @@
+use crate::error::Error;
+use crate::fstats_f64::Stats;
+
+/// Statistics of one position of a rolling window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RollingSummary {
+    pub count: usize,
+    pub mean: f64,
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Iterator adapter yielding a `RollingSummary` for every position of a fixed-size window.
+///
+/// The window is a `Stats` whose data serves as a ring buffer of `window` slots: once it is
+/// full each new value overwrites the oldest one in place, so the caches follow the window
+/// without shifting any data. The first `window - 1` summaries cover the partial windows at
+/// the start of the series.
+#[derive(Debug)]
+pub struct RollingStats<I> {
+    iter: I,
+    capacity: usize,
+    // Slot holding the oldest value once the buffer is full
+    head: usize,
+    window: Stats,
+}
+
+impl<I: Iterator<Item = f64>> Iterator for RollingStats<I> {
+    type Item = RollingSummary;
+
+    fn next(&mut self) -> Option<RollingSummary> {
+        let x = self.iter.next()?;
+        if self.window.len() < self.capacity {
+            self.window.push(x);
+        } else {
+            self.window.set(self.head, x);
+            self.head = (self.head + 1) % self.capacity;
+        }
+        Some(RollingSummary {
+            count: self.window.len(),
+            mean: self.window.mean().unwrap(),
+            stddev: self.window.stddev().unwrap(),
+            min: self.window.min().unwrap(),
+            max: self.window.max().unwrap(),
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+pub trait RollingStatsExt: Iterator<Item = f64> + Sized {
+    fn rolling_stats(self, window: usize) -> Result<RollingStats<Self>, Error> {
+        if window == 0 {
+            return Err(Error::InvalidParameter("rolling window must not be empty"));
+        }
+        Ok(RollingStats {
+            iter: self,
+            capacity: window,
+            head: 0,
+            window: Stats::new(),
+        })
+    }
+}
+
+impl<I: Iterator<Item = f64>> RollingStatsExt for I {}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+    use super::RollingStatsExt;
+    use crate::stats::{mean, stddev, min, max};
+
+    #[test]
+    fn rolling_stats_test() {
+        let vs: Vec<f64> = (0..40).map(|i| ((i * 17) % 13) as f64 - 6.0).collect();
+        let window = 7;
+        let summaries: Vec<_> = vs.iter().copied().rolling_stats(window).unwrap().collect();
+
+        assert_eq!(summaries.len(), vs.len());
+        for (i, s) in summaries.iter().enumerate() {
+            let start = (i + 1).saturating_sub(window);
+            let head = &vs[start..i + 1];
+            assert_eq!(s.count, head.len());
+            assert!( approx_eq!(f64, mean(head).unwrap(), s.mean, epsilon = 1e-12) );
+            assert!( approx_eq!(f64, stddev(head).unwrap(), s.stddev, epsilon = 1e-12) );
+            assert_eq!(min(head).unwrap(), s.min);
+            assert_eq!(max(head).unwrap(), s.max);
+        }
+    }
+
+    #[test]
+    fn window_of_one_test() {
+        let vs = vec![3.0, 1.0, 2.0];
+        let means: Vec<f64> = vs.clone().into_iter().rolling_stats(1).unwrap().map(|s| s.mean).collect();
+
+        assert_eq!(vs, means);
+    }
+
+    #[test]
+    fn empty_window_test() {
+        assert!(vec![1.0].into_iter().rolling_stats(0).is_err());
+    }
+}
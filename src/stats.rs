@@ -35,4 +35,207 @@ pub fn max(data: &[f64]) -> Option<f64> {
     } else {
         None
     }
-}
\ No newline at end of file
+}
+
+pub fn quantile(data: &[f64], q: f64) -> Option<f64> {
+    if data.is_empty() || !(0.0..=1.0).contains(&q) {
+        return None;
+    }
+    let mut sorted = data.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let index = q * (sorted.len() - 1) as f64;
+    let lower = index.floor() as usize;
+    let upper = index.ceil() as usize;
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * (index - lower as f64))
+}
+
+pub fn covariance(x: &[f64], y: &[f64]) -> Option<f64> {
+    match (mean(x), mean(y)) {
+        (Some(mean_x), Some(mean_y)) if x.len() == y.len() => {
+            let sum: f64 = x.iter().zip(y.iter())
+                .map(|(a, b)| (a - mean_x) * (b - mean_y))
+                .sum();
+            Some(sum / x.len() as f64)
+        },
+        _ => None
+    }
+}
+
+pub fn correlation(x: &[f64], y: &[f64]) -> Option<f64> {
+    match (covariance(x, y), stddev(x), stddev(y)) {
+        (Some(cov), Some(sx), Some(sy)) if sx > 0.0 && sy > 0.0 => Some(cov / (sx * sy)),
+        _ => None
+    }
+}
+
+pub fn linear_regression(x: &[f64], y: &[f64]) -> Option<(f64, f64)> {
+    match (covariance(x, y), stddev(x), mean(x), mean(y)) {
+        (Some(cov), Some(sx), Some(mean_x), Some(mean_y)) if sx > 0.0 => {
+            let slope = cov / (sx * sx);
+            Some((slope, mean_y - slope * mean_x))
+        },
+        _ => None
+    }
+}
+
+// Ranks starting at one, with tied values sharing the average of their ranks.
+fn ranks(data: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..data.len()).collect();
+    order.sort_by(|a, b| data[*a].total_cmp(&data[*b]));
+    let mut ranks = vec![0.0; data.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && data[order[end]] == data[order[start]] {
+            end += 1;
+        }
+        let rank = (start + end + 1) as f64 / 2.0;
+        order[start..end].iter().for_each(|i| ranks[*i] = rank);
+        start = end;
+    }
+    ranks
+}
+
+pub fn spearman(x: &[f64], y: &[f64]) -> Option<f64> {
+    if x.len() != y.len() {
+        return None;
+    }
+    correlation(&ranks(x), &ranks(y))
+}
+
+pub(crate) fn sign(d: f64) -> f64 {
+    if d > 0.0 {
+        1.0
+    } else if d < 0.0 {
+        -1.0
+    } else {
+        0.0
+    }
+}
+
+pub fn kendall(x: &[f64], y: &[f64]) -> Option<f64> {
+    if x.len() != y.len() || x.len() < 2 {
+        return None;
+    }
+    let (mut s, mut ties_x, mut ties_y) = (0.0, 0.0, 0.0);
+    for i in 0..x.len() {
+        for j in 0..i {
+            let dx = x[i] - x[j];
+            let dy = y[i] - y[j];
+            s += sign(dx) * sign(dy);
+            if dx == 0.0 {
+                ties_x += 1.0;
+            }
+            if dy == 0.0 {
+                ties_y += 1.0;
+            }
+        }
+    }
+    let pairs = (x.len() * (x.len() - 1)) as f64 / 2.0;
+    let denominator = f64::sqrt((pairs - ties_x) * (pairs - ties_y));
+    match denominator {
+        d if d > 0.0 => Some(s / d),
+        _ => None
+    }
+}
+
+pub fn acf(data: &[f64], max_lag: usize) -> Option<Vec<f64>> {
+    let data_mean = mean(data)?;
+    let denominator: f64 = data.iter().map(|x| (x - data_mean) * (x - data_mean)).sum();
+    if data.len() <= max_lag || denominator <= 0.0 {
+        return None;
+    }
+    Some((0..=max_lag).map(|k| {
+        data.iter().zip(data[k..].iter())
+            .map(|(a, b)| (a - data_mean) * (b - data_mean))
+            .sum::<f64>() / denominator
+    }).collect())
+}
+
+pub fn cross_correlation(a: &[f64], b: &[f64], lag: isize) -> Option<f64> {
+    let shift = lag.unsigned_abs();
+    if a.len() != b.len() || shift >= a.len() {
+        return None;
+    }
+    let n = a.len();
+    if lag >= 0 {
+        correlation(&a[..n - shift], &b[shift..])
+    } else {
+        correlation(&a[shift..], &b[..n - shift])
+    }
+}
+
+pub fn median(data: &[f64]) -> Option<f64> {
+    quantile(data, 0.5)
+}
+
+pub fn mad(data: &[f64]) -> Option<f64> {
+    let data_median = median(data)?;
+    let deviations: Vec<f64> = data.iter().map(|x| (x - data_median).abs()).collect();
+    median(&deviations)
+}
+
+pub fn iqr(data: &[f64]) -> Option<f64> {
+    Some(quantile(data, 0.75)? - quantile(data, 0.25)?)
+}
+
+// The values sorted, with floor(proportion * n) of them to be cut or clamped at each end.
+fn trimmed(data: &[f64], proportion: f64) -> Option<(Vec<f64>, usize)> {
+    if data.is_empty() || !(0.0..0.5).contains(&proportion) {
+        return None;
+    }
+    let mut sorted = data.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let g = (proportion * data.len() as f64).floor() as usize;
+    Some((sorted, g))
+}
+
+pub fn trimmed_mean(data: &[f64], proportion: f64) -> Option<f64> {
+    let (sorted, g) = trimmed(data, proportion)?;
+    mean(&sorted[g..sorted.len() - g])
+}
+
+fn winsorized(data: &[f64], proportion: f64) -> Option<Vec<f64>> {
+    let (mut sorted, g) = trimmed(data, proportion)?;
+    let n = sorted.len();
+    let (low, high) = (sorted[g], sorted[n - 1 - g]);
+    sorted[..g].iter_mut().for_each(|x| *x = low);
+    sorted[n - g..].iter_mut().for_each(|x| *x = high);
+    Some(sorted)
+}
+
+pub fn winsorized_mean(data: &[f64], proportion: f64) -> Option<f64> {
+    mean(&winsorized(data, proportion)?)
+}
+
+pub fn winsorized_variance(data: &[f64], proportion: f64) -> Option<f64> {
+    stddev(&winsorized(data, proportion)?).map(|s| s * s)
+}
+
+pub fn power_mean(data: &[f64], p: f64) -> Option<f64> {
+    if data.is_empty() || data.iter().any(|x| x.is_nan() || *x <= 0.0) {
+        return None;
+    }
+    let count = data.len() as f64;
+    if p == 0.0 {
+        Some((data.iter().map(|x| x.ln()).sum::<f64>() / count).exp())
+    } else {
+        Some((data.iter().map(|x| x.powf(p)).sum::<f64>() / count).powf(1.0 / p))
+    }
+}
+
+pub fn geometric_mean(data: &[f64]) -> Option<f64> {
+    power_mean(data, 0.0)
+}
+
+pub fn harmonic_mean(data: &[f64]) -> Option<f64> {
+    power_mean(data, -1.0)
+}
+
+pub fn quadratic_mean(data: &[f64]) -> Option<f64> {
+    if data.is_empty() {
+        None
+    } else {
+        Some((data.iter().map(|x| x * x).sum::<f64>() / data.len() as f64).sqrt())
+    }
+}
@@ -0,0 +1,420 @@
+use std::cmp::Ordering;
+use std::f64;
+use std::f64::consts::PI;
+
+use crate::accumulator::Accumulator;
+use crate::error::Error;
+use crate::quantile::QuantileSketch;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Centroid {
+    pub mean: f64,
+    pub weight: f64,
+}
+
+/// Merging t-digest for approximate quantiles of an unbounded stream.
+///
+/// Values are buffered and periodically merged into centroids whose size is bounded by the
+/// arcsine scale function, which keeps the tails more accurate than the middle of the
+/// distribution. A larger `compression` keeps more centroids and gives more accurate quantiles.
+#[derive(Clone, Debug)]
+pub struct TDigest {
+    compression: f64,
+    // Buffered values that trigger a merge into the centroids
+    buffer_limit: usize,
+    centroids: Vec<Centroid>,
+    buffer: Vec<Centroid>,
+    length: usize,
+    max: Option<f64>,
+    min: Option<f64>,
+}
+
+impl Default for TDigest {
+    fn default() -> Self {
+        TDigest::with_compression(100.0)
+    }
+}
+
+impl TDigest {
+    /// Creates an empty digest. The compression must be finite and positive.
+    pub fn new(compression: f64) -> Result<Self, Error> {
+        if !compression.is_finite() || compression <= 0.0 {
+            return Err(Error::InvalidParameter("compression must be finite and positive"));
+        }
+        Ok(TDigest::with_compression(compression))
+    }
+
+    fn with_compression(compression: f64) -> Self {
+        TDigest {
+            compression,
+            buffer_limit: (compression.ceil() as usize).saturating_mul(5),
+            centroids: vec![],
+            buffer: vec![],
+            length: 0,
+            max: None,
+            min: None,
+        }
+    }
+
+    fn k(&self, q: f64) -> f64 {
+        self.compression / (2.0 * PI) * f64::asin(2.0 * q - 1.0)
+    }
+
+    fn k_inverse(&self, k: f64) -> f64 {
+        (f64::sin(f64::min(k * 2.0 * PI / self.compression, PI / 2.0)) + 1.0) / 2.0
+    }
+
+    fn add_cache(&mut self, c: Centroid, length: usize) {
+        self.buffer.push(c);
+        self.length += length;
+        if self.buffer.len() >= self.buffer_limit {
+            self.compress();
+        }
+    }
+
+    /// Merges any buffered values into the centroids.
+    pub fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut all = std::mem::take(&mut self.buffer);
+        all.append(&mut self.centroids);
+        all.sort_by(|a, b| a.mean.total_cmp(&b.mean));
+
+        let total: f64 = all.iter().map(|c| c.weight).sum();
+        let mut merged = Vec::with_capacity(all.len());
+        let mut iter = all.into_iter();
+        let mut current = iter.next().unwrap();
+        let mut weight_so_far = 0.0;
+        let mut q_limit = self.k_inverse(self.k(0.0) + 1.0);
+        for c in iter {
+            if (weight_so_far + current.weight + c.weight) / total <= q_limit {
+                let weight = current.weight + c.weight;
+                current.mean += (c.mean - current.mean) * c.weight / weight;
+                current.weight = weight;
+            } else {
+                weight_so_far += current.weight;
+                merged.push(current);
+                q_limit = self.k_inverse(self.k(weight_so_far / total) + 1.0);
+                current = c;
+            }
+        }
+        merged.push(current);
+        self.centroids = merged;
+    }
+
+    pub fn push(&mut self, x: f64) {
+        if self.max.is_none() || self.max < Some(x) {
+            self.max = Some(x)
+        };
+        if self.min.is_none() || self.min > Some(x) {
+            self.min = Some(x)
+        };
+        self.add_cache(Centroid { mean: x, weight: 1.0 }, 1);
+    }
+
+    pub fn push_vec(&mut self, v: Vec<f64>) {
+        v.iter().for_each(|x| self.push(*x));
+    }
+
+    pub fn merge(&mut self, other: &TDigest) {
+        if other.length == 0 {
+            return;
+        }
+        if self.max.is_none() || self.max < other.max {
+            self.max = other.max
+        };
+        if self.min.is_none() || self.min > other.min {
+            self.min = other.min
+        };
+        self.length += other.length;
+        self.buffer.extend(other.centroids.iter().chain(other.buffer.iter()));
+        self.compress();
+    }
+
+    pub fn reset(&mut self) {
+        self.centroids = vec![];
+        self.buffer = vec![];
+        self.length = 0;
+        self.max = None;
+        self.min = None;
+    }
+
+    pub fn compression(&self) -> f64 {
+        self.compression
+    }
+
+    pub fn centroids(&mut self) -> &[Centroid] {
+        self.compress();
+        &self.centroids
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        self.min
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        self.max
+    }
+
+    // Each centroid sits at the middle of its cumulative weight, with the extremes pinned
+    // at either end; quantiles and the cdf interpolate linearly between these points.
+    fn points(&self) -> Vec<(f64, f64)> {
+        let mut points = Vec::with_capacity(self.centroids.len() + 2);
+        points.push((0.0, self.min.unwrap()));
+        let mut weight_so_far = 0.0;
+        for c in self.centroids.iter() {
+            points.push((weight_so_far + c.weight / 2.0, c.mean));
+            weight_so_far += c.weight;
+        }
+        points.push((weight_so_far, self.max.unwrap()));
+        points
+    }
+
+    pub fn quantile(&mut self, q: f64) -> Option<f64> {
+        if self.length == 0 || !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+        self.compress();
+        let points = self.points();
+        let index = q * self.length as f64;
+        for pair in points.windows(2) {
+            let (t0, x0) = pair[0];
+            let (t1, x1) = pair[1];
+            if index <= t1 {
+                if t1 - t0 <= 0.0 {
+                    return Some(x1);
+                }
+                return Some(x0 + (x1 - x0) * (index - t0) / (t1 - t0));
+            }
+        }
+        self.max
+    }
+
+    pub fn cdf(&mut self, x: f64) -> Option<f64> {
+        if self.length == 0 {
+            return None;
+        }
+        if x < self.min.unwrap() {
+            return Some(0.0);
+        }
+        if x >= self.max.unwrap() {
+            return Some(1.0);
+        }
+        self.compress();
+        let points = self.points();
+        let total = self.length as f64;
+        for pair in points.windows(2) {
+            let (t0, x0) = pair[0];
+            let (t1, x1) = pair[1];
+            if x < x1 {
+                if x1 - x0 <= 0.0 {
+                    return Some(t0 / total);
+                }
+                return Some((t0 + (t1 - t0) * (x - x0) / (x1 - x0)) / total);
+            }
+        }
+        Some(1.0)
+    }
+
+    /// Serializes the digest as little-endian compression, length, min, max and centroid pairs.
+    pub fn to_bytes(&mut self) -> Vec<u8> {
+        self.compress();
+        let mut bytes = Vec::with_capacity(40 + 16 * self.centroids.len());
+        bytes.extend_from_slice(&self.compression.to_le_bytes());
+        bytes.extend_from_slice(&(self.length as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.min.unwrap_or(f64::NAN).to_le_bytes());
+        bytes.extend_from_slice(&self.max.unwrap_or(f64::NAN).to_le_bytes());
+        bytes.extend_from_slice(&(self.centroids.len() as u64).to_le_bytes());
+        for c in self.centroids.iter() {
+            bytes.extend_from_slice(&c.mean.to_le_bytes());
+            bytes.extend_from_slice(&c.weight.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut words = bytes.chunks_exact(8).map(|w| {
+            let mut word = [0; 8];
+            word.copy_from_slice(w);
+            word
+        });
+        let mut next = || words.next().ok_or(Error::InvalidEncoding);
+
+        let compression = f64::from_le_bytes(next()?);
+        let length = u64::from_le_bytes(next()?) as usize;
+        let min = f64::from_le_bytes(next()?);
+        let max = f64::from_le_bytes(next()?);
+        let n = u64::from_le_bytes(next()?) as usize;
+        if !compression.is_finite() || compression <= 0.0
+            || n.checked_mul(16).and_then(|b| b.checked_add(40)) != Some(bytes.len()) {
+            return Err(Error::InvalidEncoding);
+        }
+        let mut centroids = Vec::with_capacity(n);
+        for _ in 0..n {
+            let mean = f64::from_le_bytes(next()?);
+            let weight = f64::from_le_bytes(next()?);
+            centroids.push(Centroid { mean, weight });
+        }
+        // Weights must be positive, means sorted and the weights must add up to the length.
+        let total: f64 = centroids.iter().map(|c| c.weight).sum();
+        if centroids.iter().any(|c| !c.weight.is_finite() || c.weight <= 0.0)
+            || centroids.windows(2).any(|w| w[0].mean.total_cmp(&w[1].mean) == Ordering::Greater)
+            || (total - length as f64).abs() > 1e-9 * total {
+            return Err(Error::InvalidEncoding);
+        }
+
+        let mut digest = TDigest::with_compression(compression);
+        digest.centroids = centroids;
+        digest.length = length;
+        if length > 0 {
+            digest.min = Some(min);
+            digest.max = Some(max);
+        }
+        Ok(digest)
+    }
+}
+
+impl Accumulator for TDigest {
+    fn push(&mut self, x: f64) {
+        TDigest::push(self, x);
+    }
+}
+
+impl QuantileSketch for TDigest {
+    fn merge(&mut self, other: &Self) {
+        TDigest::merge(self, other);
+    }
+
+    fn quantile(&mut self, q: f64) -> Option<f64> {
+        TDigest::quantile(self, q)
+    }
+
+    fn rank(&mut self, x: f64) -> Option<f64> {
+        self.cdf(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+    use super::TDigest;
+    use crate::test_util::values;
+    use crate::error::Error;
+    use crate::stats::quantile;
+
+    #[test]
+    fn quantile_test() {
+        let vs = values(20000, 1);
+        let mut td = TDigest::new(100.0).unwrap();
+        td.push_vec(vs.clone());
+
+        for q in [0.001, 0.01, 0.1, 0.25, 0.5, 0.75, 0.9, 0.99, 0.999].iter() {
+            let exact = quantile(&vs, *q).unwrap();
+            let approx = td.quantile(*q).unwrap();
+            let tolerance = if *q < 0.02 || *q > 0.98 { 1e-3 } else { 1e-2 };
+            assert!( approx_eq!(f64, exact, approx, epsilon = tolerance), "q = {}", q );
+        }
+        assert_eq!(td.quantile(0.0), td.min());
+        assert_eq!(td.quantile(1.0), td.max());
+        assert_eq!(td.quantile(1.5), None);
+        assert!(td.centroids().len() < 200);
+    }
+
+    #[test]
+    fn cdf_test() {
+        let vs = values(20000, 2);
+        let mut td = TDigest::new(100.0).unwrap();
+        td.push_vec(vs);
+
+        for x in [0.05, 0.3, 0.5, 0.8, 0.95].iter() {
+            assert!( approx_eq!(f64, td.cdf(*x).unwrap(), *x, epsilon = 1e-2), "x = {}", x );
+        }
+        assert_eq!(td.cdf(-1.0), Some(0.0));
+        assert_eq!(td.cdf(2.0), Some(1.0));
+    }
+
+    #[test]
+    fn merge_test() {
+        let vs = values(30000, 3);
+        let mut td = TDigest::new(100.0).unwrap();
+        for chunk in vs.chunks(10000) {
+            let mut shard = TDigest::new(100.0).unwrap();
+            shard.push_vec(chunk.to_vec());
+            td.merge(&shard);
+        }
+
+        assert_eq!(td.len(), vs.len());
+        for q in [0.01, 0.5, 0.99].iter() {
+            let exact = quantile(&vs, *q).unwrap();
+            assert!( approx_eq!(f64, exact, td.quantile(*q).unwrap(), epsilon = 1e-2) );
+        }
+    }
+
+    #[test]
+    fn small_test() {
+        let mut td = TDigest::default();
+        assert_eq!(td.quantile(0.5), None);
+        assert_eq!(td.cdf(0.0), None);
+
+        td.push_vec(vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(td.quantile(0.5), Some(3.0));
+        assert_eq!(td.cdf(3.0), Some(0.5));
+    }
+
+    #[test]
+    fn bytes_test() {
+        let mut td = TDigest::new(50.0).unwrap();
+        td.push_vec(values(5000, 4));
+        let bytes = td.to_bytes();
+        let mut rt = TDigest::from_bytes(&bytes).unwrap();
+
+        assert_eq!(rt.len(), td.len());
+        assert_eq!(rt.compression(), td.compression());
+        assert_eq!(rt.min(), td.min());
+        assert_eq!(rt.max(), td.max());
+        assert_eq!(rt.quantile(0.9), td.quantile(0.9));
+        assert!(TDigest::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut infinite = bytes.clone();
+        infinite[..8].copy_from_slice(&f64::INFINITY.to_le_bytes());
+        assert_eq!(TDigest::from_bytes(&infinite).unwrap_err(), Error::InvalidEncoding);
+
+        // Offsets of the mean and weight of the first centroid, and of the length
+        let (mean, weight, length) = (40, 48, 8);
+        for bad in [0.0, -1.0, f64::NAN].iter() {
+            let mut corrupt = bytes.clone();
+            corrupt[weight..weight + 8].copy_from_slice(&bad.to_le_bytes());
+            assert_eq!(TDigest::from_bytes(&corrupt).unwrap_err(), Error::InvalidEncoding);
+        }
+        let mut unsorted = bytes.clone();
+        unsorted[mean..mean + 8].copy_from_slice(&2.0f64.to_le_bytes());
+        assert_eq!(TDigest::from_bytes(&unsorted).unwrap_err(), Error::InvalidEncoding);
+        let mut short = bytes.clone();
+        short[length..length + 8].copy_from_slice(&4999u64.to_le_bytes());
+        assert_eq!(TDigest::from_bytes(&short).unwrap_err(), Error::InvalidEncoding);
+
+        let mut empty = TDigest::new(50.0).unwrap();
+        assert_eq!(TDigest::from_bytes(&empty.to_bytes()).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn compression_test() {
+        for compression in [0.0, -1.0, f64::NAN, f64::INFINITY].iter() {
+            assert!(TDigest::new(*compression).is_err());
+        }
+
+        // A huge compression saturates the buffer limit instead of overflowing.
+        let mut td = TDigest::new(1e300).unwrap();
+        td.push_vec(vec![1.0, 2.0, 3.0]);
+        assert_eq!(td.quantile(0.5), Some(2.0));
+    }
+}
@@ -0,0 +1,8 @@
+// Deterministic values spread over [0, 1) from a 64-bit LCG, shared by the unit tests.
+pub(crate) fn values(n: usize, seed: u64) -> Vec<f64> {
+    let mut state = seed;
+    (0..n).map(|_| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 11) as f64 / (1u64 << 53) as f64
+    }).collect()
+}